@@ -1,46 +1,83 @@
 use cb00::{
-    parts::{Draw, Prepare},
+    parts::{
+        keymap::Action,
+        settings::{self, Settings},
+        ui,
+        Draw,
+        Prepare,
+        Update,
+    },
     App,
 };
 use graphics::clear;
 use piston_window::{
+    keyboard::ModifierKey,
     AdvancedWindow,
     Button,
     EventLoop,
     IdleEvent,
-    Key,
     MouseCursorEvent,
     MouseScrollEvent,
     PistonWindow,
     PressEvent,
+    ReleaseEvent,
     RenderEvent,
     ResizeEvent,
     UpdateEvent,
 };
 use sdl2::video::FullscreenType;
 use sdl2_window::Sdl2Window;
+use std::{env, path::PathBuf};
 
 // #[tokio::main(flavor = "current_thread")]
 #[tokio::main]
 async fn main() { run().await }
 
 async fn run() {
+    if env::args_os().any(|a| a == "--print-default-config") {
+        print!("{}", settings::default_config());
+        return;
+    }
     let mut app = App::default();
-    app.add_folder(std::env::args().nth(1).unwrap_or_else(|| ".".to_string()));
+    match Settings::load() {
+        Ok((settings, problems)) => {
+            problems.iter().for_each(|p| eprintln!("{}", p));
+            app.settings = settings;
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+    // Paths aren't necessarily UTF-8, they are passed on as they are.
+    let path = env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."));
+    if let Err(e) = app.add_folder(path) {
+        eprintln!("{}", e);
+        return;
+    }
     // app.test().await;
     let mut window: PistonWindow<Sdl2Window> =
         app.settings.window.build().unwrap();
     window.set_capture_cursor(app.settings.capture);
     window.set_max_fps(app.settings.fps);
     window.set_ups(app.settings.ups);
+    let mut glyphs = ui::font(&mut window);
+    let mut modifiers = ModifierKey::default();
     //main loop
     while let Some(e) = window.next() {
         let mut ctx = window.create_texture_context();
         app.prepare(&mut ctx);
+        app.take_skipped().iter().for_each(|s| eprint!("{}", s));
 
-        window.draw_2d(&e, |c, g, _device| {
+        window.draw_2d(&e, |c, g, device| {
             clear([0.0; 4], g);
-            app.draw(c, g, None);
+            app.draw(c, g, glyphs.as_mut());
+            if let Some(glyphs) = &mut glyphs {
+                glyphs.factory.encoder.flush(device);
+            }
         });
         if let Some(_) = e.resize_args() {
             app.resize(&window);
@@ -48,21 +85,21 @@ async fn run() {
         if let Some(pos) = e.mouse_cursor(|xy| xy) {
             app.cursor(pos);
         };
-        e.mouse_scroll(|d| {
-            d[1];
-        });
-        if let Some(button) = e.press_args() {
-            if let Button::Keyboard(key) = button {
-                match key {
-                    Key::A | Key::Left => app.prev_page(),
-                    Key::D | Key::Right => app.next_page(),
-                    Key::W | Key::Up | Key::NumPadPlus => app.more(),
-                    Key::S | Key::Down | Key::NumPadMinus => app.less(),
-                    Key::R => app.toggle_direction(),
-                    Key::Q => break,
-                    Key::F | Key::F12 => fullscreen(&mut window),
-                    _ => {}
-                }
+        modifiers.event(&e);
+        if let Some(d) = e.mouse_scroll_args() {
+            app.wheel(d[1], modifiers.contains(ModifierKey::CTRL));
+        }
+        if let Some(Button::Mouse(button)) = e.press_args() {
+            app.press(button);
+        }
+        if let Some(Button::Mouse(button)) = e.release_args() {
+            app.release(button);
+        }
+        if let Some(Button::Keyboard(key)) = e.press_args() {
+            match app.key(key, modifiers) {
+                Some(Action::Quit) => break,
+                Some(Action::Fullscreen) => fullscreen(&mut window),
+                _ => {}
             }
         }
         if let Some(_args) = e.idle_args() {
@@ -71,9 +108,13 @@ async fn run() {
         if let Some(_args) = e.render_args() {
             // app.render(&args);
         }
-        if let Some(_args) = e.update_args() {
-            // println!("{}", args.dt);
-            // app.update();
+        if let Some(args) = e.update_args() {
+            app.update(args.dt);
+            match app.take_action() {
+                Some(Action::Quit) => break,
+                Some(Action::Fullscreen) => fullscreen(&mut window),
+                _ => {}
+            }
         }
     }
 }
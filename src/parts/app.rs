@@ -1,5 +1,14 @@
 use super::{
-    common::{Draw, Prepare},
+    common::{Draw, Prepare, Update},
+    detect::Skipped,
+    error::Result,
+    input::{Wheel, Zone},
+    keymap::{Action, Chord},
+    layout::{Direction, Fit, Mode},
+    library::Library,
+    sequence::Sequence,
+    settings::Settings,
+    ui::Osd,
     Folder,
 };
 use average::WeightedMean;
@@ -8,51 +17,50 @@ use gfx_graphics::GfxGraphics;
 use graphics::Context;
 use header::HeaderValue;
 use levenshtein::levenshtein as lev;
-use piston_window::{OpenGL, PistonWindow, Size, Window, WindowSettings};
+use piston_window::{
+    keyboard::ModifierKey,
+    Glyphs,
+    Key,
+    MouseButton,
+    PistonWindow,
+    Size,
+    Window,
+};
 use reqwest::{header, Client, Url};
 use sdl2_window::Sdl2Window;
 use select::{document::Document, predicate::Name};
-use std::{cmp::max, collections::HashMap, fmt::Debug, fs::File, io::Write};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+// Pixels per wheel click and the part of the window an arrow key scrolls.
+const WHEEL_STEP: f64 = 120.;
+const KEY_STEP: f64 = 0.2;
+// Factor of one zoom step.
+const ZOOM_STEP: f64 = 1.25;
+// Pixels the cursor can move between a left press and its release for it to
+// still count as a click rather than a drag.
+const CLICK_SLOP: f64 = 4.;
 
-impl Default for Settings {
-    fn default() -> Self {
-        let fullscreen = false;
-        let vsync = false;
-        let capture = false;
-        let esc_exit = true;
-        let transparent = true;
-        let ups = 30;
-        let fps = max(ups * 2, 60);
-        let samples = 16;
-        let opengl = OpenGL::V4_5;
-        let mut window = WindowSettings::new("Reader", [1., 1.])
-            .exit_on_esc(esc_exit)
-            .samples(samples)
-            .vsync(vsync)
-            .graphics_api(opengl);
-        window.set_transparent(transparent);
-        Self {
-            window,
-            fullscreen,
-            vsync,
-            capture,
-            esc_exit,
-            transparent,
-            ups,
-            fps,
-            samples,
-            opengl,
-        }
-    }
-}
 impl Default for App {
     fn default() -> Self {
         Self {
             title:    "Reader".to_string(),
             current:  0,
             panes:    HashMap::new(),
+            library:  HashMap::new(),
+            osd:      Osd::default(),
+            sequence: Sequence::default(),
+            deferred: None,
             settings: Settings::default(),
+            skipped:  vec![],
             cursor:   [0.; 2],
+            dragging: false,
+            pressed:  None,
+            ui:       true,
             width:    1.,
             height:   1.,
             ar:       1.,
@@ -61,27 +69,26 @@ impl Default for App {
     }
 }
 
-#[derive(Clone)]
-pub struct Settings {
-    pub fullscreen:  bool,
-    pub vsync:       bool,
-    pub capture:     bool,
-    pub esc_exit:    bool,
-    pub transparent: bool,
-    pub ups:         u64,
-    pub fps:         u64,
-    pub samples:     u8,
-    pub opengl:      OpenGL,
-    pub window:      WindowSettings,
-    //add new fields to Debug impl
-}
 #[derive(Clone, Debug)]
 pub struct App {
     pub title:    String,
     current:      u16,
     panes:        HashMap<u16, Vec<Folder>>,
+    library:      HashMap<u16, Library>,
+    pub osd:      Osd,
+    sequence:     Sequence,
+    // Quit or fullscreen fired by the sequence timing out, for the caller.
+    deferred:     Option<Action>,
     pub settings: Settings,
+    // Reports of skipped files, see `take_skipped`.
+    skipped:      Vec<Skipped>,
     cursor:       [f64; 2],
+    // Held mouse button, the view follows the cursor.
+    dragging:     bool,
+    // Where the left button went down, for telling clicks from drags.
+    pressed:      Option<[f64; 2]>,
+    // Messages are drawn, hidden to see the pages alone.
+    ui:           bool,
     pub width:    f64,
     pub height:   f64,
     pub ar:       f64,
@@ -172,45 +179,265 @@ impl App {
 
     pub fn add_folder(
         &mut self,
-        path: String,
-    ) {
+        path: PathBuf,
+    ) -> Result<()> {
+        // A directory of chapters opens on its first chapter, a lone chapter
+        // gets its siblings so reading can carry on into the next one.
+        let filter = &self.settings.filter;
+        let library = match Library::scan(&path, filter) {
+            Some(library) if library.chapters().len() > 1 => Some(library),
+            library => Library::siblings(&path, filter).or(library),
+        };
+        let path = match library {
+            Some(library) => {
+                let first = library.chapter().to_path_buf();
+                self.library.insert(self.current, library);
+                first
+            }
+            None => path,
+        };
+        let folder = self.folder(&path)?;
         self.panes
             .entry(self.current)
-            .or_insert_with(|| vec![Folder::new(&path)]);
+            .or_insert_with(|| vec![folder]);
+        Ok(())
     }
 
-    pub fn next_page(&mut self) {
-        self.panes
+    fn folder(
+        &self,
+        path: &Path,
+    ) -> Result<Folder> {
+        let mut folder = Folder::new(path)?;
+        if let Some(library) = self.library.get(&self.current) {
+            folder.set_root(&library.root.path);
+        }
+        folder.set_filter(self.settings.filter.clone());
+        folder.set_prefetch(self.settings.prefetch);
+        folder.set_budget(self.settings.budget);
+        folder.set_limits(self.settings.limits);
+        Ok(folder)
+    }
+
+    pub fn next_chapter(&mut self) -> bool {
+        match self
+            .library
+            .get_mut(&self.current)
+            .and_then(|l| l.next_chapter())
+            .map(Path::to_path_buf)
+        {
+            Some(path) => self.open_chapter(&path),
+            None => false,
+        }
+    }
+
+    pub fn prev_chapter(&mut self) -> bool {
+        match self
+            .library
+            .get_mut(&self.current)
+            .and_then(|l| l.prev_chapter())
+            .map(Path::to_path_buf)
+        {
+            Some(path) => self.open_chapter(&path),
+            None => false,
+        }
+    }
+
+    fn open_chapter(
+        &mut self,
+        path: &Path,
+    ) -> bool {
+        let mut chapter = match self.folder(path) {
+            Ok(chapter) => chapter,
+            Err(e) => {
+                self.osd.show(e.to_string());
+                return false;
+            }
+        };
+        match self
+            .panes
+            .get_mut(&self.current)
+            .and_then(|p| p.first_mut())
+        {
+            Some(folder) => {
+                chapter.keep_view(folder);
+                *folder = chapter;
+                self.osd.show(folder.name());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Paging past either end of a chapter continues in the adjacent one.
+    // False when there is nowhere left to go, as for the other moves.
+    pub fn next_page(&mut self) -> bool {
+        let moved = self
+            .panes
             .get_mut(&self.current)
             .unwrap()
             .first_mut()
             .unwrap()
             .next_page();
+        moved || self.next_chapter()
     }
 
-    pub fn prev_page(&mut self) {
-        self.panes
+    pub fn prev_page(&mut self) -> bool {
+        let moved = self
+            .panes
             .get_mut(&self.current)
             .unwrap()
             .first_mut()
             .unwrap()
             .prev_page();
+        if moved {
+            return true;
+        }
+        let opened = self.prev_chapter();
+        if opened {
+            self.panes
+                .get_mut(&self.current)
+                .unwrap()
+                .first_mut()
+                .unwrap()
+                .last_page();
+        }
+        opened
+    }
+
+    // Feeds a key press to the pending sequence and runs the action it
+    // completes. Quit and fullscreen are returned for the caller instead.
+    pub fn key(
+        &mut self,
+        key: Key,
+        mods: ModifierKey,
+    ) -> Option<Action> {
+        let chord = Chord { key, mods };
+        let (action, count) = self.sequence.feed(chord, &self.settings.keys)?;
+        self.run(action, count)
+    }
+
+    // A count is the page for `GoTo`, moves are repeated that many times,
+    // see `Action::repeat`. Repeats stop once nothing moves, or at the end
+    // of a chapter so paging doesn't run on through the ones after it.
+    fn run(
+        &mut self,
+        action: Action,
+        count: Option<usize>,
+    ) -> Option<Action> {
+        match action {
+            Action::Quit | Action::Fullscreen => return Some(action),
+            Action::GoTo => self.go_to(count.unwrap_or(1)),
+            _ => {
+                let path = self.first_folder().map(|f| f.path().to_string());
+                for _ in 0..action.repeat(count) {
+                    let moved = self.dispatch(action);
+                    let here = self.first_folder().map(|f| f.path().to_string());
+                    if !moved || here != path {
+                        break;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Quit and fullscreen need the window and are left to the caller.
+    // Whether the action moved anything, the ones that aren't repeated
+    // always count as done.
+    pub fn dispatch(
+        &mut self,
+        action: Action,
+    ) -> bool {
+        match action {
+            Action::Left => return self.left(),
+            Action::Right => return self.right(),
+            Action::Up => return self.up(),
+            Action::Down => return self.down(),
+            Action::NextPage => return self.next_page(),
+            Action::PrevPage => return self.prev_page(),
+            Action::NextChapter => return self.next_chapter(),
+            Action::PrevChapter => return self.prev_chapter(),
+            Action::More => return self.more(),
+            Action::Less => return self.less(),
+            Action::ZoomIn => return self.zoom_in(),
+            Action::ZoomOut => return self.zoom_out(),
+            Action::PanLeft => return self.pan_left(),
+            Action::PanRight => return self.pan_right(),
+            Action::PanUp => return self.pan_up(),
+            Action::PanDown => return self.pan_down(),
+            Action::FirstPage => self.go_to(1),
+            Action::LastPage => self.last_page(),
+            Action::GoTo => self.go_to(1),
+            Action::ToggleDirection => self.toggle_direction(),
+            Action::ToggleSpread => self.toggle_spread(),
+            Action::ToggleCover => self.toggle_cover(),
+            Action::ToggleStrip => self.toggle_strip(),
+            Action::NextFit => self.next_fit(),
+            Action::FitOriginal => self.set_fit(Fit::Original),
+            Action::NextSort => self.next_sort(),
+            Action::ReverseSort => self.reverse_sort(),
+            Action::ToggleAnimation => self.toggle_animation(),
+            Action::StepFrame => self.step_frame(),
+            Action::ToggleUi => self.toggle_ui(),
+            Action::Fullscreen | Action::Quit => {}
+        }
+        true
     }
 
-    pub fn more(&mut self) {
+    pub fn go_to(
+        &mut self,
+        page: usize,
+    ) {
+        if let Some(folder) = self.first_folder() {
+            folder.go_to(page);
+        }
+    }
+
+    pub fn last_page(&mut self) {
+        if let Some(folder) = self.first_folder() {
+            folder.last_page();
+        }
+    }
+
+    // Arrow keys follow the reading direction of the current folder.
+    pub fn left(&mut self) -> bool {
+        match self.direction() {
+            Direction::Ltr => self.prev_page(),
+            Direction::Rtl => self.next_page(),
+        }
+    }
+
+    pub fn right(&mut self) -> bool {
+        match self.direction() {
+            Direction::Ltr => self.next_page(),
+            Direction::Rtl => self.prev_page(),
+        }
+    }
+
+    fn direction(&self) -> Direction {
+        self.panes
+            .get(&self.current)
+            .and_then(|p| p.first())
+            .map(Folder::direction)
+            .unwrap_or_default()
+    }
+
+    pub fn more(&mut self) -> bool {
         self.panes
             .get_mut(&self.current)
             .unwrap()
             .iter_mut()
-            .for_each(|f| f.more())
+            .map(Folder::more)
+            .fold(false, |a, b| a || b)
     }
 
-    pub fn less(&mut self) {
+    pub fn less(&mut self) -> bool {
         self.panes
             .get_mut(&self.current)
             .unwrap()
             .iter_mut()
-            .for_each(|f| f.less())
+            .map(Folder::less)
+            .fold(false, |a, b| a || b)
     }
 
     pub fn toggle_direction(&mut self) {
@@ -219,16 +446,273 @@ impl App {
             .unwrap()
             .iter_mut()
             .for_each(|f| f.toggle_direction());
+        self.osd.show(match self.direction() {
+            Direction::Ltr => "left to right",
+            Direction::Rtl => "right to left",
+        });
+    }
+
+    pub fn toggle_spread(&mut self) {
+        if let Some(folder) = self.first_folder() {
+            folder.toggle_spread();
+            let text = match folder.mode() {
+                Mode::Spread { .. } => "two page spreads",
+                _ => "rows",
+            };
+            self.osd.show(text);
+        }
+    }
+
+    pub fn toggle_strip(&mut self) {
+        if let Some(folder) = self.first_folder() {
+            folder.toggle_strip();
+            let text = match folder.mode() {
+                Mode::Strip => "vertical strip",
+                _ => "rows",
+            };
+            self.osd.show(text);
+        }
+    }
+
+    // Wheel clicks, positive when rolled away from the user. With ctrl held
+    // they zoom, the strip always scrolls, other modes do as configured.
+    pub fn wheel(
+        &mut self,
+        clicks: f64,
+        ctrl: bool,
+    ) {
+        let mode = match self.first_folder() {
+            Some(folder) => folder.mode(),
+            None => return,
+        };
+        match (ctrl, mode, self.settings.mouse.wheel) {
+            (true, ..) => match clicks > 0. {
+                true => self.zoom_in(),
+                false => self.zoom_out(),
+            },
+            (_, Mode::Strip, _) => self.scroll(-clicks * WHEEL_STEP),
+            (_, _, Wheel::Scroll) => self.pan(0., clicks * WHEEL_STEP),
+            (_, _, Wheel::Page) => match clicks > 0. {
+                true => self.prev_page(),
+                false => self.next_page(),
+            },
+        };
+    }
+
+    fn scroll(
+        &mut self,
+        dy: f64,
+    ) -> bool {
+        self.first_folder()
+            .map(|f| f.scroll(dy))
+            .unwrap_or_default()
+    }
+
+    // Left click at the cursor, see `Mouse::zones`.
+    fn click(&mut self) {
+        if !self.settings.mouse.zones {
+            return;
+        }
+        match Zone::at(self.cursor[0], self.width) {
+            Zone::Left => {
+                self.left();
+            }
+            Zone::Right => {
+                self.right();
+            }
+            Zone::Center => self.toggle_ui(),
+        }
+    }
+
+    pub fn toggle_ui(&mut self) { self.ui ^= true; }
+
+    // Up and down scroll the strip, otherwise they change the page count.
+    pub fn up(&mut self) -> bool {
+        match self.first_folder().map(|f| f.mode()) {
+            Some(Mode::Strip) => self.scroll(-self.height * KEY_STEP),
+            _ => self.more(),
+        }
+    }
+
+    pub fn down(&mut self) -> bool {
+        match self.first_folder().map(|f| f.mode()) {
+            Some(Mode::Strip) => self.scroll(self.height * KEY_STEP),
+            _ => self.less(),
+        }
+    }
+
+    pub fn set_fit(
+        &mut self,
+        fit: Fit,
+    ) {
+        if let Some(folder) = self.first_folder() {
+            folder.set_fit(fit);
+            self.show_zoom();
+        }
+    }
+
+    pub fn next_fit(&mut self) {
+        if let Some(folder) = self.first_folder() {
+            folder.set_fit(folder.fit().next());
+            self.show_zoom();
+        }
+    }
+
+    // Zooms towards whatever is under the cursor.
+    pub fn zoom_in(&mut self) -> bool { self.zoom(ZOOM_STEP) }
+
+    pub fn zoom_out(&mut self) -> bool { self.zoom(1. / ZOOM_STEP) }
+
+    fn zoom(
+        &mut self,
+        factor: f64,
+    ) -> bool {
+        let at = self.cursor;
+        let zoomed = self
+            .first_folder()
+            .map(|f| f.zoom_at(factor, at))
+            .unwrap_or_default();
+        self.show_zoom();
+        zoomed
+    }
+
+    fn show_zoom(&mut self) {
+        let text = match self.first_folder().map(|f| f.fit()) {
+            Some(Fit::Page) => "fit page".to_string(),
+            Some(Fit::Width) => "fit width".to_string(),
+            Some(Fit::Height) => "fit height".to_string(),
+            Some(Fit::Original) => "original size".to_string(),
+            Some(Fit::Zoom(zoom)) => format!("{:.0}%", zoom * 100.),
+            None => return,
+        };
+        self.osd.show(text);
+    }
+
+    // Keys move the view by a part of the window, the page moves the other
+    // way to show what is past the edge.
+    pub fn pan_left(&mut self) -> bool { self.pan(self.width * KEY_STEP, 0.) }
+
+    pub fn pan_right(&mut self) -> bool { self.pan(-self.width * KEY_STEP, 0.) }
+
+    pub fn pan_up(&mut self) -> bool { self.pan(0., self.height * KEY_STEP) }
+
+    pub fn pan_down(&mut self) -> bool { self.pan(0., -self.height * KEY_STEP) }
+
+    fn pan(
+        &mut self,
+        dx: f64,
+        dy: f64,
+    ) -> bool {
+        self.first_folder()
+            .map(|f| f.pan(dx, dy))
+            .unwrap_or_default()
+    }
+
+    // Left and middle both drag the view, a left press is only a click
+    // once it is released without having moved.
+    pub fn press(
+        &mut self,
+        button: MouseButton,
+    ) {
+        match button {
+            MouseButton::Left => {
+                self.pressed = Some(self.cursor);
+                self.dragging = true;
+            }
+            MouseButton::Middle => self.dragging = true,
+            _ => {}
+        }
+    }
+
+    pub fn release(
+        &mut self,
+        button: MouseButton,
+    ) {
+        match button {
+            MouseButton::Left => {
+                self.dragging = false;
+                if let Some([x, y]) = self.pressed.take() {
+                    let moved = (self.cursor[0] - x).hypot(self.cursor[1] - y);
+                    if moved <= CLICK_SLOP {
+                        self.click();
+                    }
+                }
+            }
+            MouseButton::Middle => self.dragging = false,
+            _ => {}
+        }
+    }
+
+    pub fn toggle_cover(&mut self) {
+        if let Some(folder) = self.first_folder() {
+            folder.toggle_cover();
+            if let Mode::Spread { cover } = folder.mode() {
+                self.osd.show(match cover {
+                    true => "cover alone",
+                    false => "cover paired",
+                });
+            }
+        }
+    }
+
+    fn first_folder(&mut self) -> Option<&mut Folder> {
+        self.panes
+            .get_mut(&self.current)
+            .and_then(|p| p.first_mut())
+    }
+
+    pub fn toggle_animation(&mut self) {
+        self.panes
+            .get_mut(&self.current)
+            .unwrap()
+            .iter_mut()
+            .for_each(|f| f.toggle_animation());
+    }
+
+    pub fn step_frame(&mut self) {
+        self.panes
+            .get_mut(&self.current)
+            .unwrap()
+            .iter_mut()
+            .for_each(|f| f.step_frame());
+    }
+
+    pub fn next_sort(&mut self) {
+        self.panes
+            .get_mut(&self.current)
+            .unwrap()
+            .iter_mut()
+            .for_each(|f| f.next_sort());
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.panes
+            .get_mut(&self.current)
+            .unwrap()
+            .iter_mut()
+            .for_each(|f| f.reverse_sort());
     }
 
     pub fn cursor<'a>(
         &'a mut self,
         cursor: [f64; 2],
     ) -> &'a [f64; 2] {
+        let [dx, dy] = [cursor[0] - self.cursor[0], cursor[1] - self.cursor[1]];
         self.cursor = cursor;
+        if self.dragging {
+            self.pan(dx, dy);
+        }
         &self.cursor
     }
 
+    // Like the result of `key`, for a binding that fired on `update`.
+    pub fn take_action(&mut self) -> Option<Action> { self.deferred.take() }
+
+    // What folders left out since the last call, the OSD only shows a count.
+    pub fn take_skipped(&mut self) -> Vec<Skipped> {
+        std::mem::take(&mut self.skipped)
+    }
+
     pub fn resize(
         &mut self,
         window: &PistonWindow<Sdl2Window>,
@@ -248,41 +732,52 @@ impl<'a> Prepare<'a> for App {
     ) {
         for item in self.panes.values_mut().into_iter().flatten() {
             // item.download(&self.client, None);
-            item.prepare((ctx, self.width, self.height));
+            if let Err(e) = item.prepare((ctx, self.width, self.height)) {
+                self.osd.show(e.to_string());
+            }
+            if let Some(skipped) = item.take_report() {
+                let n = skipped.0.len();
+                let s = if n == 1 { "" } else { "s" };
+                self.osd.show(format!("{} file{} skipped", n, s));
+                self.skipped.push(skipped);
+            }
         }
     }
 }
-impl Draw<'_> for App {
-    fn draw(
-        &self,
-        c: Context,
-        g: &mut GfxGraphics<Resources, CommandBuffer>,
-        _: Self::Params,
+impl Update for App {
+    type Input = f64;
+
+    fn update(
+        &mut self,
+        dt: Self::Input,
     ) {
-        self.panes
-            .get(&self.current)
-            .unwrap()
-            .iter()
-            .for_each(|a| a.draw(c, g, (self.width, self.height)));
+        if let Some(pane) = self.panes.get_mut(&self.current) {
+            pane.iter_mut().for_each(|f| f.update(dt));
+        }
+        if let Some((action, count)) = self.sequence.tick(&self.settings.keys) {
+            self.deferred = self.run(action, count);
+        }
     }
 }
+impl<'a> Draw<'a> for App {
+    type Params = Option<&'a mut Glyphs>;
 
-impl Debug for Settings {
-    fn fmt(
+    fn draw(
         &self,
-        f: &mut std::fmt::Formatter<'_>,
-    ) -> std::fmt::Result {
-        f.debug_struct("Settings")
-            .field("fullscreen", &self.fullscreen)
-            .field("vsync", &self.vsync)
-            .field("capture", &self.capture)
-            .field("esc_exit", &self.esc_exit)
-            .field("transparent", &self.transparent)
-            .field("ups", &self.ups)
-            .field("fps", &self.fps)
-            .field("samples", &self.samples)
-            .field("opengl", &self.opengl)
-            .finish()
+        c: Context,
+        g: &mut GfxGraphics<Resources, CommandBuffer>,
+        mut glyphs: Self::Params,
+    ) {
+        for folder in self.panes.get(&self.current).into_iter().flatten() {
+            folder.draw(c, g, glyphs.as_deref_mut());
+        }
+        match glyphs {
+            Some(glyphs) if self.ui => {
+                self.osd.draw(c, g, glyphs);
+                self.sequence.draw(c, g, glyphs);
+            }
+            _ => {}
+        }
     }
 }
 
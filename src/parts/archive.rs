@@ -0,0 +1,202 @@
+use super::detect::{Filter, Format, Skip, HEAD};
+use flate2::read::GzDecoder;
+use sevenz_rust::{Password, SevenZReader};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use zip::ZipArchive;
+
+const ARCHIVES: [&str; 6] = ["cbz", "zip", "cbt", "tar", "cb7", "7z"];
+pub fn is_archive(s: &str) -> bool {
+    ARCHIVES.iter().any(|a| a.eq_ignore_ascii_case(s))
+}
+
+// The handles are shared so a cloned Folder reads from the same open archive.
+#[derive(Clone, Debug)]
+pub enum Archive {
+    // CBZ is read in place, its central directory lists every file.
+    Zip(Arc<Mutex<ZipArchive<File>>>),
+    // Tar and 7z have no central directory, so they are scanned once into a
+    // list of (name, offset, size) for every file. Compressed tars and 7z are
+    // unpacked into an anonymous spool file on the way, plain tars are read
+    // in place.
+    Indexed(Arc<Mutex<File>>, Vec<(String, u64, u64)>),
+}
+// A listing of the files, see `Archive::entries`.
+pub type Entries = Vec<(String, u64, Result<Format, Skip>)>;
+
+impl Archive {
+    // Unlike CBZ, a compressed CBT or a CB7 is spooled to a temporary file
+    // as it is indexed, which reads the whole archive and takes as much disk
+    // space as its contents. A plain CBT is only scanned for its headers.
+    // Folders therefore open archives on the worker pool.
+    // Files larger than `cap` bytes aren't spooled, they are left out.
+    pub fn open(
+        path: &Path,
+        cap: u64,
+    ) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "cbz" | "zip" => Some(Self::Zip(Arc::new(Mutex::new(
+                ZipArchive::new(File::open(path).ok()?).ok()?,
+            )))),
+            "cbt" | "tar" => Self::tar(path, cap).ok(),
+            "cb7" | "7z" => Self::seven(path, cap).ok(),
+            _ => None,
+        }
+    }
+
+    fn tar(
+        path: &Path,
+        cap: u64,
+    ) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut magic = [0; 4];
+        let n = file.read(&mut magic)?;
+        file.seek(SeekFrom::Start(0))?;
+        match &magic[..n] {
+            [0x1f, 0x8b, ..] => Self::spool_tar(GzDecoder::new(file), cap),
+            [0x28, 0xb5, 0x2f, 0xfd] => {
+                Self::spool_tar(zstd::stream::read::Decoder::new(file)?, cap)
+            }
+            _ => {
+                let mut index = vec![];
+                for entry in tar::Archive::new(&file).entries()? {
+                    let entry = entry?;
+                    let name = entry.path()?.to_string_lossy().into_owned();
+                    if entry.header().entry_type().is_file() {
+                        index.push((
+                            name,
+                            entry.raw_file_position(),
+                            entry.size(),
+                        ));
+                    }
+                }
+                Ok(Self::Indexed(Arc::new(Mutex::new(file)), index))
+            }
+        }
+    }
+
+    fn spool_tar(
+        reader: impl Read,
+        cap: u64,
+    ) -> io::Result<Self> {
+        let mut spool = tempfile::tempfile()?;
+        let mut index = vec![];
+        for entry in tar::Archive::new(reader).entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if entry.header().entry_type().is_file() && entry.size() <= cap {
+                let offset = spool.seek(SeekFrom::End(0))?;
+                let size = io::copy(&mut (&mut entry).take(cap), &mut spool)?;
+                index.push((name, offset, size));
+            }
+        }
+        Ok(Self::Indexed(Arc::new(Mutex::new(spool)), index))
+    }
+
+    fn seven(
+        path: &Path,
+        cap: u64,
+    ) -> io::Result<Self> {
+        let other = |e: sevenz_rust::Error| io::Error::other(e.to_string());
+        let mut reader =
+            SevenZReader::open(path, Password::empty()).map_err(other)?;
+        let mut spool = tempfile::tempfile()?;
+        let mut index = vec![];
+        reader
+            .for_each_entries(|entry, data| {
+                let page = entry.has_stream() && !entry.is_directory();
+                if page && entry.size() <= cap {
+                    let offset = spool
+                        .seek(SeekFrom::End(0))
+                        .map_err(sevenz_rust::Error::io)?;
+                    let size = io::copy(&mut (&mut *data).take(cap), &mut spool)
+                        .map_err(sevenz_rust::Error::io)?;
+                    index.push((entry.name().to_string(), offset, size));
+                }
+                // Solid blocks have to be drained to reach the next entry.
+                io::copy(data, &mut io::sink())
+                    .map_err(sevenz_rust::Error::io)?;
+                Ok(true)
+            })
+            .map_err(other)?;
+        Ok(Self::Indexed(Arc::new(Mutex::new(spool)), index))
+    }
+
+    /// Every file in archive order with its uncompressed size and whether the
+    /// filter takes it as a page, judged by the first bytes of its contents.
+    pub fn entries(
+        &self,
+        filter: &Filter,
+    ) -> Entries {
+        match self {
+            Self::Zip(zip) => {
+                let mut zip = zip.lock().unwrap();
+                (0..zip.len())
+                    .filter_map(|i| {
+                        let mut file = zip.by_index(i).ok()?;
+                        let name = file.name().to_string();
+                        let mut head = Vec::with_capacity(HEAD);
+                        let kind = match file.is_file() {
+                            true => (&mut file)
+                                .take(HEAD as u64)
+                                .read_to_end(&mut head)
+                                .map(|_| filter.bytes(&head, &name))
+                                .unwrap_or_else(|e| {
+                                    Err(Skip::Unreadable(e.to_string()))
+                                }),
+                            false => return None,
+                        };
+                        Some((name, file.size(), kind))
+                    })
+                    .collect()
+            }
+            Self::Indexed(file, index) => {
+                let mut file = file.lock().unwrap();
+                index
+                    .iter()
+                    .map(|(name, offset, size)| {
+                        let mut head = vec![0; HEAD.min(*size as usize)];
+                        let kind = file
+                            .seek(SeekFrom::Start(*offset))
+                            .and_then(|_| file.read_exact(&mut head))
+                            .map(|_| filter.bytes(&head, name))
+                            .unwrap_or_else(|e| {
+                                Err(Skip::Unreadable(e.to_string()))
+                            });
+                        (name.clone(), *size, kind)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // At most the first `n` bytes of a file, only those get decompressed.
+    pub fn head(
+        &self,
+        name: &str,
+        n: u64,
+    ) -> Option<Vec<u8>> {
+        match self {
+            Self::Zip(zip) => {
+                let mut zip = zip.lock().unwrap();
+                let file = zip.by_name(name).ok()?;
+                let mut buf = Vec::with_capacity(file.size().min(n) as usize);
+                file.take(n).read_to_end(&mut buf).ok()?;
+                Some(buf)
+            }
+            Self::Indexed(file, index) => {
+                let (_, offset, size) = index.iter().find(|e| e.0 == name)?;
+                let mut file = file.lock().unwrap();
+                let mut buf = vec![0; (*size).min(n) as usize];
+                file.seek(SeekFrom::Start(*offset)).ok()?;
+                file.read_exact(&mut buf).ok()?;
+                Some(buf)
+            }
+        }
+    }
+}
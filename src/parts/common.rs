@@ -18,11 +18,12 @@ pub trait Draw<'a> {
 }
 pub trait Prepare<'a> {
     type Input = &'a mut G2dTextureContext;
+    type Output = ();
 
     fn prepare(
         &mut self,
         _: Self::Input,
-    );
+    ) -> Self::Output;
 }
 pub trait Update {
     type Input;
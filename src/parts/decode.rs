@@ -0,0 +1,342 @@
+use super::{
+    detect::Format,
+    error::{Error, Result},
+};
+use exif::{In, Reader, Tag};
+use image::{
+    codecs::{gif::GifDecoder, png::PngDecoder},
+    imageops,
+    AnimationDecoder,
+    Frames,
+    ImageDecoder,
+    ImageFormat,
+    RgbaImage,
+};
+use image_webp::WebPDecoder;
+use jxl_oxide::JxlImage;
+use std::{
+    fmt::Display,
+    io::{BufRead, Cursor, Seek},
+    time::{Duration, Instant},
+};
+
+// Browsers show frames with next to no delay for 100ms, so do the same.
+const MIN_DELAY: Duration = Duration::from_millis(10);
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+// Caps on what a single page may cost, pages can come from any website.
+// The size is checked from the header before anything is allocated, the
+// decoded bytes and time after every frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    pub width:  u32,
+    pub height: u32,
+    // Of all frames together, at four bytes a pixel.
+    pub bytes:  u64,
+    pub time:   Duration,
+}
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            width:  16384,
+            height: 65535,
+            bytes:  1 << 30,
+            time:   Duration::from_secs(10),
+        }
+    }
+}
+impl Limits {
+    pub fn check(
+        &self,
+        (w, h): (u32, u32),
+    ) -> Result<()> {
+        if w > self.width || h > self.height {
+            return Err(Error::Limit(format!(
+                "{}x{} is over the {}x{} limit",
+                w, h, self.width, self.height
+            )));
+        }
+        self.meter().add(w as u64 * h as u64 * 4)
+    }
+
+    fn meter(&self) -> Meter {
+        Meter {
+            limits: *self,
+            start:  Instant::now(),
+            bytes:  0,
+        }
+    }
+}
+
+// Running totals of a single decode, checked against its limits.
+struct Meter {
+    limits: Limits,
+    start:  Instant,
+    bytes:  u64,
+}
+impl Meter {
+    fn add(
+        &mut self,
+        bytes: u64,
+    ) -> Result<()> {
+        self.bytes += bytes;
+        if self.bytes > self.limits.bytes {
+            return Err(Error::Limit(format!(
+                "over {} decoded bytes",
+                self.limits.bytes
+            )));
+        }
+        if self.start.elapsed() > self.limits.time {
+            return Err(Error::Limit(format!(
+                "decoding took over {:?}",
+                self.limits.time
+            )));
+        }
+        Ok(())
+    }
+
+    fn frame(
+        &mut self,
+        img: RgbaImage,
+        delay: Duration,
+    ) -> Result<(RgbaImage, Duration)> {
+        self.add(img.len() as u64)?;
+        Ok((img, delay))
+    }
+}
+
+// A page ready for upload, along with what the info panel shows about it.
+#[derive(Clone, Debug, Default)]
+pub struct Page {
+    pub frames: Vec<(RgbaImage, Duration)>,
+    // Bits per pixel of the source, before the conversion to RGBA.
+    pub depth:  Option<u16>,
+    // Tag names with their values formatted for display.
+    pub exif:   Vec<(String, String)>,
+}
+
+fn err(e: impl Display) -> Error { Error::Decode(e.to_string()) }
+
+// Animated formats only yield their first frame here.
+pub fn decode(
+    bytes: &[u8],
+    format: Format,
+) -> Result<RgbaImage> {
+    match format {
+        Format::Image(f) => image::load_from_memory_with_format(bytes, f)
+            .map(|img| img.to_rgba8())
+            .map_err(err),
+        Format::WebP => webp(bytes),
+        Format::Jxl => jxl(bytes),
+    }
+}
+
+// Reads only as much of the header as it takes to find the size.
+pub fn dimensions(
+    reader: impl BufRead + Seek,
+    format: Format,
+) -> Result<(u32, u32)> {
+    match format {
+        Format::Image(f) => image::io::Reader::with_format(reader, f)
+            .into_dimensions()
+            .map_err(err),
+        Format::WebP => WebPDecoder::new(reader)
+            .map(|d| d.dimensions())
+            .map_err(err),
+        Format::Jxl => JxlImage::builder()
+            .read(reader)
+            .map(|i| (i.width(), i.height()))
+            .map_err(err),
+    }
+}
+
+// Every frame with how long it stays up, turned upright according to the
+// EXIF orientation. A still image is a single frame.
+pub fn page(
+    bytes: &[u8],
+    format: Format,
+    limits: &Limits,
+) -> Result<Page> {
+    limits.check(dimensions(Cursor::new(bytes), format)?)?;
+    let (frames, depth) = frames(bytes, format, limits.meter())?;
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(bytes))
+        .ok();
+    let orientation = exif
+        .as_ref()
+        .and_then(|e| e.get_field(Tag::Orientation, In::PRIMARY))
+        .and_then(|f| f.value.get_uint(0))
+        .unwrap_or(1);
+    Ok(Page {
+        frames: frames
+            .into_iter()
+            .map(|(img, delay)| (orient(img, orientation), delay))
+            .collect(),
+        depth,
+        exif: exif
+            .as_ref()
+            .map(|e| {
+                e.fields()
+                    .map(|f| {
+                        let value = f.display_value().with_unit(e);
+                        (f.tag.to_string(), value.to_string())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default(),
+    })
+}
+
+fn frames(
+    bytes: &[u8],
+    format: Format,
+    mut meter: Meter,
+) -> Result<(Vec<(RgbaImage, Duration)>, Option<u16>)> {
+    match format {
+        Format::Image(ImageFormat::Gif) => {
+            let gif = GifDecoder::new(Cursor::new(bytes)).map_err(err)?;
+            let depth = gif.color_type().bits_per_pixel();
+            Ok((collect(gif.into_frames(), meter)?, Some(depth)))
+        }
+        Format::Image(ImageFormat::Png) => {
+            let png = PngDecoder::new(Cursor::new(bytes)).map_err(err)?;
+            let depth = png.color_type().bits_per_pixel();
+            match png.is_apng() {
+                true => {
+                    Ok((collect(png.apng().into_frames(), meter)?, Some(depth)))
+                }
+                false => still(bytes, format, meter),
+            }
+        }
+        Format::WebP => webp_frames(bytes, &mut meter),
+        _ => still(bytes, format, meter),
+    }
+}
+
+// A single call into the decoder can't be interrupted, so a slow still is
+// only rejected once it is done.
+fn still(
+    bytes: &[u8],
+    format: Format,
+    mut meter: Meter,
+) -> Result<(Vec<(RgbaImage, Duration)>, Option<u16>)> {
+    let (img, depth) = match format {
+        Format::Image(f) => {
+            let img =
+                image::load_from_memory_with_format(bytes, f).map_err(err)?;
+            let depth = img.color().bits_per_pixel();
+            (img.to_rgba8(), Some(depth))
+        }
+        _ => (decode(bytes, format)?, None),
+    };
+    Ok((vec![meter.frame(img, Duration::ZERO)?], depth))
+}
+
+// Orientation values as defined by EXIF, 1 is already upright.
+fn orient(
+    img: RgbaImage,
+    orientation: u32,
+) -> RgbaImage {
+    match orientation {
+        2 => imageops::flip_horizontal(&img),
+        3 => imageops::rotate180(&img),
+        4 => imageops::flip_vertical(&img),
+        5 => imageops::flip_horizontal(&imageops::rotate90(&img)),
+        6 => imageops::rotate90(&img),
+        7 => imageops::flip_horizontal(&imageops::rotate270(&img)),
+        8 => imageops::rotate270(&img),
+        _ => img,
+    }
+}
+
+// Frames are pulled one at a time so a bomb stops at the first one over.
+fn collect(
+    frames: Frames,
+    mut meter: Meter,
+) -> Result<Vec<(RgbaImage, Duration)>> {
+    frames
+        .map(|f| {
+            let f = f.map_err(err)?;
+            let (n, d) = f.delay().numer_denom_ms();
+            meter.frame(f.into_buffer(), delay(n / d.max(1)))
+        })
+        .collect()
+}
+
+fn webp_frames(
+    bytes: &[u8],
+    meter: &mut Meter,
+) -> Result<(Vec<(RgbaImage, Duration)>, Option<u16>)> {
+    let mut decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(err)?;
+    let alpha = decoder.has_alpha();
+    let depth = Some(if alpha { 32 } else { 24 });
+    if !decoder.is_animated() {
+        let frame = meter.frame(webp(bytes)?, Duration::ZERO)?;
+        return Ok((vec![frame], depth));
+    }
+    let (w, h) = decoder.dimensions();
+    let size = decoder
+        .output_buffer_size()
+        .ok_or_else(|| err("too large"))?;
+    let frames = (0..decoder.num_frames())
+        .map(|_| {
+            let mut buf = vec![0; size];
+            let ms = decoder.read_frame(&mut buf).map_err(err)?;
+            meter.frame(rgba(w, h, buf, alpha)?, delay(ms))
+        })
+        .collect::<Result<_>>()?;
+    Ok((frames, depth))
+}
+
+fn delay(ms: u32) -> Duration {
+    match Duration::from_millis(ms as u64) {
+        d if d <= MIN_DELAY => DEFAULT_DELAY,
+        d => d,
+    }
+}
+
+fn rgba(
+    w: u32,
+    h: u32,
+    mut buf: Vec<u8>,
+    alpha: bool,
+) -> Result<RgbaImage> {
+    if !alpha {
+        buf = buf
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect();
+    }
+    RgbaImage::from_raw(w, h, buf).ok_or_else(|| err("bad buffer"))
+}
+
+fn webp(bytes: &[u8]) -> Result<RgbaImage> {
+    let mut decoder = WebPDecoder::new(Cursor::new(bytes)).map_err(err)?;
+    let (w, h) = decoder.dimensions();
+    let size = decoder
+        .output_buffer_size()
+        .ok_or_else(|| err("too large"))?;
+    let mut buf = vec![0; size];
+    decoder.read_image(&mut buf).map_err(err)?;
+    rgba(w, h, buf, decoder.has_alpha())
+}
+
+fn jxl(bytes: &[u8]) -> Result<RgbaImage> {
+    let image = JxlImage::builder().read(bytes).map_err(err)?;
+    let render = image.render_frame(0).map_err(err)?;
+    let fb = render.image_all_channels();
+    let (w, h, c) = (fb.width() as u32, fb.height() as u32, fb.channels());
+    let px = |v: f32| (v.max(0.).min(1.) * 255. + 0.5) as u8;
+    let buf = fb
+        .buf()
+        .chunks_exact(c)
+        .flat_map(|p| match p {
+            [y] => [px(*y), px(*y), px(*y), 255],
+            [y, a] => [px(*y), px(*y), px(*y), px(*a)],
+            [r, g, b] => [px(*r), px(*g), px(*b), 255],
+            [r, g, b, a, ..] => [px(*r), px(*g), px(*b), px(*a)],
+            _ => [0; 4],
+        })
+        .collect();
+    RgbaImage::from_raw(w, h, buf).ok_or_else(|| err("bad buffer"))
+}
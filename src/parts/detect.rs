@@ -0,0 +1,240 @@
+use image::ImageFormat;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// Enough for every signature `sniff` knows about.
+pub const HEAD: usize = 16;
+
+// Formats decoded by the `image` crate are kept as its own `ImageFormat`,
+// the rest have their own decoders in `decode`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Image(ImageFormat),
+    WebP,
+    Jxl,
+}
+impl Format {
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Image(f) => f.extensions_str(),
+            Self::WebP => &["webp"],
+            Self::Jxl => &["jxl"],
+        }
+    }
+
+    pub fn can_read(self) -> bool {
+        match self {
+            Self::Image(f) => f.can_read(),
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Skip {
+    Unreadable(String),
+    NotImage,
+    Unsupported(Format),
+    Denied(String),
+}
+impl Display for Skip {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Unreadable(e) => write!(f, "unreadable: {}", e),
+            Self::NotImage => write!(f, "not an image"),
+            Self::Unsupported(format) => {
+                write!(f, "{:?} can't be decoded", format)
+            }
+            Self::Denied(by) => write!(f, "denied by \"{}\"", by),
+        }
+    }
+}
+
+// Formats are named by any of their extensions, so "jpg" and "jpeg" are the
+// same entry. An empty allow list lets every decodable format through, the
+// deny list also applies to the file's own extension.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    pub allow: Vec<String>,
+    pub deny:  Vec<String>,
+}
+impl Filter {
+    pub fn path(
+        &self,
+        path: &Path,
+    ) -> Result<Format, Skip> {
+        let mut head = Vec::with_capacity(HEAD);
+        File::open(path)
+            .and_then(|f| f.take(HEAD as u64).read_to_end(&mut head))
+            .map_err(|e| Skip::Unreadable(e.to_string()))?;
+        self.bytes(&head, &path.to_string_lossy())
+    }
+
+    pub fn bytes(
+        &self,
+        head: &[u8],
+        name: &str,
+    ) -> Result<Format, Skip> {
+        let ext = extension(name);
+        if let Some(ext) = self.deny.iter().find(|d| d.eq_ignore_ascii_case(&ext))
+        {
+            return Err(Skip::Denied(ext.clone()));
+        }
+        let format = sniff(head, &ext).ok_or(Skip::NotImage)?;
+        let named = |list: &Vec<String>| {
+            list.iter().find(|n| {
+                format
+                    .extensions()
+                    .iter()
+                    .any(|e| e.eq_ignore_ascii_case(n))
+            })
+        };
+        if let Some(by) = named(&self.deny) {
+            return Err(Skip::Denied(by.clone()));
+        }
+        if !self.allow.is_empty() && named(&self.allow).is_none() {
+            return Err(Skip::Denied(ext));
+        }
+        match format.can_read() {
+            true => Ok(format),
+            false => Err(Skip::Unsupported(format)),
+        }
+    }
+}
+
+// The magic bytes decide, the extension only covers formats without a
+// signature such as TGA. WebP, AVIF and JPEG XL are checked before
+// `image::guess_format`, which takes any RIFF file for WebP and only knows
+// one of the AVIF header sizes.
+pub fn sniff(
+    head: &[u8],
+    ext: &str,
+) -> Option<Format> {
+    const JXL: [u8; 12] = [
+        0, 0, 0, 0x0c, b'J', b'X', b'L', b' ', 0x0d, 0x0a, 0x87, 0x0a,
+    ];
+    match head {
+        [0xff, 0x0a, ..] => return Some(Format::Jxl),
+        h if h.starts_with(&JXL) => return Some(Format::Jxl),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => {
+            return Some(Format::WebP)
+        }
+        [b'R', b'I', b'F', b'F', ..] => return None,
+        h if matches!(h.get(4..12), Some(b"ftypavif") | Some(b"ftypavis")) => {
+            return Some(Format::Image(ImageFormat::Avif))
+        }
+        _ => {}
+    }
+    image::guess_format(head)
+        .ok()
+        .map(Format::Image)
+        .or_else(|| match ext {
+            "tga" => Some(Format::Image(ImageFormat::Tga)),
+            _ => None,
+        })
+}
+
+// Lowercased, empty when there is none.
+pub fn extension(name: impl AsRef<Path>) -> String {
+    name.as_ref()
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+// Files left out of a folder and the reason for each.
+#[derive(Clone, Debug, Default)]
+pub struct Skipped(pub Vec<(PathBuf, Skip)>);
+impl Display for Skipped {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        for (path, why) in &self.0 {
+            writeln!(f, "skipped {}: {}", path.display(), why)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JPEG: &[u8] =
+        &[0xff, 0xd8, 0xff, 0xe0, 0, 0x10, b'J', b'F', b'I', b'F'];
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    const JPG: Format = Format::Image(ImageFormat::Jpeg);
+
+    fn filter(
+        allow: &[&str],
+        deny: &[&str],
+    ) -> Filter {
+        let list = |l: &[&str]| -> Vec<String> {
+            l.iter().map(|s| s.to_string()).collect()
+        };
+        Filter {
+            allow: list(allow),
+            deny:  list(deny),
+        }
+    }
+
+    #[test]
+    fn extensions_ignore_case() {
+        assert_eq!(extension("Page01.JPG"), "jpg");
+        assert_eq!(filter(&["jpeg"], &[]).bytes(JPEG, "a.JPG"), Ok(JPG));
+        assert_eq!(
+            filter(&[], &["jpg"]).bytes(JPEG, "a.JPG"),
+            Err(Skip::Denied("jpg".to_string()))
+        );
+    }
+
+    #[test]
+    fn contents_decide_over_names() {
+        assert_eq!(Filter::default().bytes(JPEG, "a.jfif"), Ok(JPG));
+        assert_eq!(filter(&["jpg"], &[]).bytes(JPEG, "a.jfif"), Ok(JPG));
+        assert_eq!(extension("page"), "");
+        assert_eq!(
+            Filter::default().bytes(PNG, "page"),
+            Ok(Format::Image(ImageFormat::Png))
+        );
+        assert_eq!(
+            Filter::default().bytes(b"plain text", "page"),
+            Err(Skip::NotImage)
+        );
+    }
+
+    #[test]
+    fn riff_is_only_webp_when_marked() {
+        let webp = b"RIFF\0\0\0\0WEBPVP8 ";
+        let wave = b"RIFF\0\0\0\0WAVEfmt ";
+        assert_eq!(sniff(webp, "webp"), Some(Format::WebP));
+        assert_eq!(sniff(wave, "webp"), None);
+        assert_eq!(Filter::default().bytes(wave, "a.wav"), Err(Skip::NotImage));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let both = filter(&["jpg"], &["jpeg"]);
+        assert_eq!(
+            both.bytes(JPEG, "a.jpg"),
+            Err(Skip::Denied("jpeg".to_string()))
+        );
+        // The file's own extension is denied before its contents are read.
+        assert_eq!(
+            filter(&[], &["wav"]).bytes(b"RIFF", "a.WAV"),
+            Err(Skip::Denied("wav".to_string()))
+        );
+        assert_eq!(
+            filter(&["png"], &[]).bytes(JPEG, "a.png"),
+            Err(Skip::Denied("png".to_string()))
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::{fmt::Display, io, path::PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(PathBuf, io::Error),
+    // Relative, or otherwise impossible to turn into a file URL and back.
+    Path(PathBuf),
+    Archive(PathBuf),
+    Entry(String),
+    Format(String),
+    Decode(String),
+    // Refused before or while decoding, see `decode::Limits`.
+    Limit(String),
+    Upload,
+    // Unreadable or invalid settings, see `settings`.
+    Config(String),
+}
+impl Display for Error {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Self::Path(path) => {
+                write!(f, "{}: not a usable path", path.display())
+            }
+            Self::Archive(path) => {
+                write!(f, "{}: unreadable archive", path.display())
+            }
+            Self::Entry(name) => write!(f, "{}: missing from the archive", name),
+            Self::Format(name) => write!(f, "{}: unknown image format", name),
+            Self::Decode(e) => write!(f, "can't decode: {}", e),
+            Self::Limit(e) => write!(f, "refused: {}", e),
+            Self::Upload => write!(f, "texture upload failed"),
+            Self::Config(e) => write!(f, "config: {}", e),
+        }
+    }
+}
+impl std::error::Error for Error {}
@@ -1,94 +1,236 @@
 use super::{
-    common::{Draw, Prepare, Source},
-    contains,
+    archive::{is_archive, Archive, Entries},
+    common::{Draw, Prepare, Source, Update},
+    decode::Limits,
+    detect::{Filter, Skipped},
+    error::{Error, Result},
+    layout::{bounds, layout, views, within, Direction, Fit, Mode, Rect},
     picture::Picture,
+    sort::SortMode,
+    state,
+    watch::{Change, Watch},
+    worker::Pool,
 };
 use gfx_device_gl::{CommandBuffer, Resources};
 use gfx_graphics::GfxGraphics;
 use graphics::Context;
 use home;
-use itertools::Itertools;
-use piston_window::G2dTextureContext;
+use indexmap::IndexMap;
+use piston_window::{G2dTextureContext, Glyphs};
 use reqwest::{header::HeaderMap, Client, Url};
-use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
+use std::{
+    cmp::Reverse,
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 use url::Origin;
 
+// Key of the folders read right to left in `state`.
+const RTL: &str = "rtl";
+// Paging in strip mode keeps this much of a window in view.
+const PAGE_STEP: f64 = 0.9;
+// How fast scrolling catches up with its target, per second.
+const SCROLL_EASE: f64 = 12.;
+// Zoom stays between these factors of the page's pixel size.
+const MIN_ZOOM: f64 = 0.05;
+const MAX_ZOOM: f64 = 16.;
+
 impl Default for Folder {
     fn default() -> Self {
+        let url = Url::from_directory_path(
+            home::home_dir().unwrap_or_else(|| PathBuf::from("/")),
+        )
+        .ok()
+        .unwrap();
         Self {
-            url:       Url::from_directory_path(
-                home::home_dir().unwrap().to_str().unwrap(),
-            )
-            .ok()
-            .unwrap(),
-            items:     BTreeMap::new(),
-            changed:   true,
-            direction: true,
-            size:      0,
-            maxdim:    (0, 0),
-            batch:     2,
-            index:     0,
-            stats:     (1, (1, 1), 0.),
+            root: url.clone(),
+            url,
+            items: IndexMap::new(),
+            changed: true,
+            direction: Direction::default(),
+            sort: SortMode::default(),
+            reverse: false,
+            archive: None,
+            opening: false,
+            version: 0,
+            filter: Filter::default(),
+            skipped: Skipped::default(),
+            report: false,
+            paused: false,
+            watch: None,
+            prefetch: 4,
+            pool: Pool::default(),
+            budget: 512 << 20,
+            limits: Limits::default(),
+            size: 0,
+            batch: 2,
+            index: 0,
+            mode: Mode::default(),
+            fit: Fit::default(),
+            zoom: 1.,
+            pan: [0.; 2],
+            layout: vec![],
+            views: vec![],
+            viewport: (1., 1.),
+            scroll: 0.,
+            target: 0.,
+            jump: None,
+            end: false,
         }
     }
 }
 #[derive(Clone, Debug)]
 pub struct Folder {
     url:       Url,
-    items:     BTreeMap<Url, Picture>,
+    // Where the reading direction is kept, the library the folder is a
+    // chapter of or else the folder itself.
+    root:      Url,
+    items:     IndexMap<Url, Picture>,
     changed:   bool,
-    direction: bool,
+    direction: Direction,
+    sort:      SortMode,
+    reverse:   bool,
+    archive:   Option<Archive>,
+    // The archive is being opened on the pool.
+    opening:   bool,
+    // Last version given to a page, see `Picture::version`.
+    version:   u64,
+    filter:    Filter,
+    skipped:   Skipped,
+    // The first read skipped files, the app hasn't taken them yet.
+    report:    bool,
+    paused:    bool,
+    watch:     Option<Watch>,
+    prefetch:  usize,
+    pool:      Pool,
+    budget:    u64,
+    limits:    Limits,
     size:      u64,
-    maxdim:    (u32, u32),
     batch:     u8,
     index:     usize,
-    stats:     (usize, (u32, u32), f64),
+    mode:      Mode,
+    fit:       Fit,
+    // Scale the fit came to on the last layout.
+    zoom:      f64,
+    // Offset of a view larger than the window, kept within its edges.
+    pan:       [f64; 2],
+    layout:    Vec<Rect>,
+    // Ranges of pages shown together, `index` picks one of them.
+    views:     Vec<Range<usize>>,
+    viewport:  (f64, f64),
+    // Strip mode only: pixels scrolled down from the top of the first page,
+    // eased towards `target`, or a page to put at the top on the next frame.
+    scroll:    f64,
+    target:    f64,
+    jump:      Option<usize>,
+    // Go to the last page once there are pages, see `last_page`.
+    end:       bool,
 }
 impl Folder {
-    pub fn new(path: &str) -> Self {
-        let p = PathBuf::from(&path);
-        let url = if p
-            .canonicalize()
-            .unwrap_or_default()
-            .to_str()
-            .expect("Failed to convert to &str.")
-            .starts_with('/')
-        {
-            Url::from_file_path(p.canonicalize().ok().unwrap().as_path())
-                .expect("couldn't parse folder path")
-        } else {
-            dbg!(&p.as_path());
-            Url::parse(path).expect("couldn't parse url path")
+    // Anything that isn't an existing local path has to parse as a URL,
+    // which only a path that is valid UTF-8 can.
+    pub fn new(path: &Path) -> Result<Self> {
+        let url = match path.canonicalize() {
+            Ok(abs) => Url::from_file_path(&abs).map_err(|_| Error::Path(abs))?,
+            Err(e) => match path.to_str().map(Url::parse) {
+                Some(Ok(url)) if url.scheme() != "file" => url,
+                _ => return Err(Error::Io(path.to_path_buf(), e)),
+            },
         };
-        Self {
+        let direction = match state::get(&url, RTL) {
+            true => Direction::Rtl,
+            false => Direction::Ltr,
+        };
+        Ok(Self {
+            root: url.clone(),
             url,
+            direction,
             ..Self::default()
+        })
+    }
+
+    // Chapters of a library share its reading direction.
+    pub fn set_root(
+        &mut self,
+        root: &Path,
+    ) {
+        let root = root.canonicalize().ok();
+        if let Some(root) = root.and_then(|r| Url::from_directory_path(r).ok()) {
+            self.direction = match state::get(&root, RTL) {
+                true => Direction::Rtl,
+                false => Direction::Ltr,
+            };
+            self.root = root;
         }
     }
 
-    pub fn read(&mut self) {
+    // Reading carries on in another chapter the way it was set up in this
+    // one, for a folder that was just created.
+    pub fn keep_view(
+        &mut self,
+        from: &Folder,
+    ) {
+        self.mode = from.mode;
+        self.fit = from.fit;
+        self.zoom = from.zoom;
+        self.sort = from.sort;
+        self.reverse = from.reverse;
+        self.batch = from.batch;
+    }
+
+    pub fn read(&mut self) -> Result<()> {
         match self.scheme() {
             "file" => {
-                let path = PathBuf::from(self.path());
+                let path = self
+                    .url
+                    .to_file_path()
+                    .map_err(|_| Error::Path(PathBuf::from(self.path())))?;
+                if path.is_file() &&
+                    is_archive(
+                        path.extension()
+                            .unwrap_or_default()
+                            .to_str()
+                            .unwrap_or(""),
+                    )
+                {
+                    self.changed = true;
+                    return self.read_archive(path);
+                }
                 let dir = match path.is_dir() {
-                    true => path.read_dir(),
-                    false => path.parent().unwrap().read_dir(),
+                    true => path.clone(),
+                    false => path
+                        .parent()
+                        .map(PathBuf::from)
+                        .ok_or_else(|| Error::Path(path.clone()))?,
                 };
-                for entry in dir.ok().unwrap().filter_map(|a| a.ok()) {
-                    entry
-                        .path()
-                        .is_file()
-                        .then_some(contains(
-                            entry
-                                .path()
-                                .extension()
-                                .unwrap_or_default()
-                                .to_str()
-                                .unwrap_or(""),
-                        ))
-                        .unwrap_or_default()
-                        .then(|| self.add_from(entry.path()));
+                if self.watch.is_none() {
+                    self.watch = Watch::new(&dir);
+                }
+                let entries =
+                    dir.read_dir().map_err(|e| Error::Io(dir.clone(), e))?;
+                let first = self.items.is_empty();
+                self.skipped.0.clear();
+                for path in entries.filter_map(|a| a.ok().map(|e| e.path())) {
+                    if !path.is_file() {
+                        continue;
+                    }
+                    match self.filter.path(&path) {
+                        Ok(format) => {
+                            let (url, pic) = page(path);
+                            let version = self.next_version();
+                            self.items.entry(url).or_insert(Picture {
+                                format: Some(format),
+                                version,
+                                ..pic
+                            });
+                        }
+                        Err(why) => self.skipped.0.push((path, why)),
+                    }
                 }
+                self.sort();
+                self.report |= first && !self.skipped.0.is_empty();
                 // dir.ok()
                 //     .unwrap()
                 //     .into_iter()
@@ -128,22 +270,293 @@ impl Folder {
             _ => {}
         }
         self.changed = true;
+        Ok(())
+    }
+
+    // Opening can take a while, see `Archive::open`, so the pages are only
+    // added once `opened` picks up the listing.
+    fn read_archive(
+        &mut self,
+        path: PathBuf,
+    ) -> Result<()> {
+        if self.archive.is_none() && !self.opening {
+            self.opening = true;
+            self.pool.open(path, self.filter.clone(), self.limits.bytes);
+        }
+        Ok(())
+    }
+
+    fn opened(&mut self) -> Result<()> {
+        for (path, opened) in self.pool.opened() {
+            self.opening = false;
+            let (archive, entries) =
+                opened.ok_or_else(|| Error::Archive(path.clone()))?;
+            self.archive = Some(archive);
+            self.list_archive(path, entries);
+            self.changed = true;
+        }
+        Ok(())
+    }
+
+    fn list_archive(
+        &mut self,
+        path: PathBuf,
+        entries: Entries,
+    ) {
+        let first = self.items.is_empty();
+        self.skipped.0.clear();
+        for (name, size, kind) in entries {
+            let virt = path.join(&name);
+            let format = match kind {
+                Ok(format) => format,
+                Err(why) => {
+                    self.skipped.0.push((virt, why));
+                    continue;
+                }
+            };
+            self.items
+                .entry(Url::from_directory_path(&virt).ok().unwrap())
+                .or_insert_with(|| Picture {
+                    path: path.clone(),
+                    size,
+                    entry: Some(name),
+                    format: Some(format),
+                    ..Picture::default()
+                });
+        }
+        self.sort();
+        self.report |= first && !self.skipped.0.is_empty();
+    }
+
+    // Applies what the watcher saw since the last frame without a full
+    // rescan, staying on the page that was being read.
+    fn watch_changes(&mut self) {
+        let changes = match &self.watch {
+            Some(watch) => watch.changes(),
+            None => return,
+        };
+        if changes.is_empty() {
+            return;
+        }
+        let anchor = self
+            .items
+            .get_index(self.shown().start)
+            .map(|(url, _)| url.clone());
+        for change in changes {
+            let path = match change {
+                Change::Removed(path) => {
+                    if let Ok(url) = Url::from_directory_path(&path) {
+                        self.items.shift_remove(&url);
+                    }
+                    continue;
+                }
+                Change::Added(path) | Change::Modified(path) => path,
+            };
+            if !path.is_file() {
+                continue;
+            }
+            match self.filter.path(&path) {
+                Ok(format) => {
+                    let (url, mut pic) = page(path);
+                    pic.format = Some(format);
+                    pic.version = self.next_version();
+                    pic.probing = true;
+                    self.pool.probe(url.clone(), pic.job(&None, self.limits));
+                    self.items.shift_remove(&url);
+                    self.items.insert(url, pic);
+                }
+                Err(why) => self.skipped.0.push((path, why)),
+            }
+        }
+        self.sort();
+        self.totals();
+        self.split();
+        if let Some(pos) = anchor.and_then(|a| self.items.get_index_of(&a)) {
+            self.show(pos);
+        }
+    }
+
+    // The pages in view and `prefetch` pages to either side of them.
+    fn window(&self) -> (usize, usize) {
+        let shown = self.shown();
+        (
+            shown.start.saturating_sub(self.prefetch),
+            shown.end + self.prefetch,
+        )
+    }
+
+    // Queues the pages in the window, everything else only has its size
+    // probed until it comes near.
+    fn load_window(&mut self) {
+        let (start, end) = self.window();
+        let (archive, pool, limits) = (&self.archive, &self.pool, self.limits);
+        let now = Instant::now();
+        self.items
+            .iter_mut()
+            .skip(start)
+            .take(end - start)
+            .for_each(|(url, pic)| {
+                pic.seen = Some(now);
+                if !pic.loaded && !pic.pending {
+                    pic.pending = true;
+                    pool.decode(url.clone(), pic.job(archive, limits));
+                }
+            });
+    }
+
+    // Sizes are read from the headers on the pool, pages are decoded once
+    // they come into view.
+    fn probe(&mut self) {
+        let (archive, pool, limits) = (&self.archive, &self.pool, self.limits);
+        self.items
+            .iter_mut()
+            .filter(|(_, pic)| (pic.w, pic.h) == (0, 0) && !pic.probing)
+            .for_each(|(url, pic)| {
+                pic.probing = true;
+                pool.probe(url.clone(), pic.job(archive, limits));
+            });
+    }
+
+    // Sizes read since the last frame, staying on the page that was being
+    // read when they change the order.
+    fn probed(&mut self) {
+        let mut sized = false;
+        for (url, version, (w, h)) in self.pool.probed() {
+            if let Some(pic) = self.items.get_mut(&url) {
+                if pic.version == version && (pic.w, pic.h) == (0, 0) {
+                    pic.w = w;
+                    pic.h = h;
+                    sized = true;
+                }
+            }
+        }
+        if !sized {
+            return;
+        }
+        self.totals();
+        if self.sort == SortMode::Dimensions {
+            let anchor = self
+                .items
+                .get_index(self.shown().start)
+                .map(|(url, _)| url.clone());
+            self.sort();
+            self.split();
+            if let Some(pos) = anchor.and_then(|a| self.items.get_index_of(&a)) {
+                self.show(pos);
+            }
+        }
+    }
+
+    // Drops the textures of the least recently seen pages outside the window,
+    // furthest from the current page first, until the folder fits its budget.
+    fn evict(&mut self) {
+        let mut used: u64 = self.items.values().map(Picture::memory).sum();
+        if used <= self.budget {
+            return;
+        }
+        let (start, end) = self.window();
+        let mut lru: Vec<_> = self
+            .items
+            .values()
+            .enumerate()
+            .filter(|(n, pic)| !(start..end).contains(n) && pic.memory() > 0)
+            .map(|(n, pic)| {
+                let far = n.max(start) - n.min(start);
+                (pic.seen, Reverse(far), n)
+            })
+            .collect();
+        lru.sort();
+        for (_, _, n) in lru {
+            if used <= self.budget {
+                break;
+            }
+            if let Some((_, pic)) = self.items.get_index_mut(n) {
+                used -= pic.memory();
+                pic.unload();
+            }
+        }
+    }
+
+    pub fn set_limits(
+        &mut self,
+        limits: Limits,
+    ) {
+        self.limits = limits;
+    }
+
+    pub fn set_budget(
+        &mut self,
+        budget: u64,
+    ) {
+        self.budget = budget;
+    }
+
+    // Pages dropped or replaced while they were decoding are simply
+    // discarded, broken ones keep their error for the placeholder.
+    fn upload(
+        &mut self,
+        ctx: &mut G2dTextureContext,
+    ) {
+        for (url, version, decoded) in self.pool.done() {
+            match self.items.get_mut(&url) {
+                Some(pic) if pic.version == version => {
+                    pic.upload(ctx, decoded).ok();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn next_version(&mut self) -> u64 {
+        self.version += 1;
+        self.version
+    }
+
+    pub fn set_prefetch(
+        &mut self,
+        prefetch: usize,
+    ) {
+        self.prefetch = prefetch;
+    }
+
+    fn totals(&mut self) {
+        self.size = self
+            .items
+            .values()
+            .fold(0, |acc, Picture { size, .. }| acc + size);
+    }
+
+    pub fn set_filter(
+        &mut self,
+        filter: Filter,
+    ) {
+        self.filter = filter;
+        self.items.clear();
+        // An open archive was listed through the old filter.
+        self.archive = None;
+        self.changed = true;
+    }
+
+    // What the first read left out, handed over once.
+    pub fn take_report(&mut self) -> Option<Skipped> {
+        match std::mem::take(&mut self.report) {
+            true => Some(self.skipped.clone()),
+            false => None,
+        }
     }
 
-    #[allow(unused_variables, unreachable_code)]
+    // Unused until pages can come from URLs, `read` fills the folder itself.
+    #[allow(dead_code, unused_variables, unreachable_code)]
     fn add_from(
         &mut self,
         link: impl Into<Source>,
     ) -> Option<Picture> {
         self.changed = true;
-        match link.into() {
-            Source::Path(pb) => self.items.insert(
-                Url::from_directory_path(&pb).ok().unwrap(),
-                Picture {
-                    path: pb,
-                    ..Picture::default()
-                },
-            ),
+        let old = match link.into() {
+            Source::Path(pb) => {
+                let (url, pic) = page(pb);
+                self.items.insert(url, pic)
+            }
             Source::Url(url) => {
                 todo!("Needs logic for temp dir allocation and file dl");
                 self.items.insert(url, Picture {
@@ -151,7 +564,9 @@ impl Folder {
                     ..Picture::default()
                 })
             }
-        }
+        };
+        self.sort();
+        old
     }
 
     #[allow(dead_code, unused_variables, unreachable_code)]
@@ -189,62 +604,340 @@ impl Folder {
 
     pub fn path(&self) -> &str { self.url.path() }
 
-    pub fn next_page(&mut self) {
-        if (self.index + 1) as usize * (self.batch as usize) <
-            self.items.values().len()
-        {
+    // Returns false when already on the last page. Until the pages are known
+    // there is no telling, so paging doesn't skip past a chapter that is
+    // still being read.
+    pub fn next_page(&mut self) -> bool {
+        if self.loading() {
+            return true;
+        }
+        if self.mode == Mode::Strip {
+            let more = self.target + self.viewport.1 < self.length() - 1.;
+            if more {
+                self.target += self.viewport.1 * PAGE_STEP;
+            }
+            return more;
+        }
+        let more = self.index + 1 < self.views.len();
+        if more {
             self.index += 1;
+            self.home();
+        }
+        more
+    }
+
+    // Returns false when already on the first page.
+    pub fn prev_page(&mut self) -> bool {
+        if self.loading() {
+            return true;
+        }
+        if self.mode == Mode::Strip {
+            let more = self.target > 0.;
+            self.target -= self.viewport.1 * PAGE_STEP;
+            return more;
+        }
+        let more = self.index > 0;
+        self.index = self.index.saturating_sub(1);
+        self.home();
+        more
+    }
+
+    // The archive is still being opened, or nothing was read yet.
+    fn loading(&self) -> bool {
+        self.opening || (self.changed && self.items.is_empty())
+    }
+
+    // Waits for the pages when they aren't known yet, as with an archive
+    // still being opened, see `split`.
+    pub fn last_page(&mut self) {
+        self.end = true;
+        self.split();
+        self.home();
+    }
+
+    // Counted from 1, past the end is the last page.
+    pub fn go_to(
+        &mut self,
+        page: usize,
+    ) {
+        let last = self.items.len().saturating_sub(1);
+        self.show(page.saturating_sub(1).min(last));
+    }
+
+    // Only moves anything in strip mode, false once at that end.
+    pub fn scroll(
+        &mut self,
+        dy: f64,
+    ) -> bool {
+        let end = (self.length() - self.viewport.1).max(0.);
+        let target = (self.target + dy).max(0.).min(end);
+        let moved = self.mode == Mode::Strip && target != self.target;
+        if moved {
+            self.target = target;
         }
+        moved
     }
 
-    pub fn prev_page(&mut self) { self.index = self.index.saturating_sub(1); }
+    // Height of the whole strip.
+    fn length(&self) -> f64 {
+        self.layout.last().map(|r| r[1] + r[3]).unwrap_or_default()
+    }
+
+    pub fn name(&self) -> String {
+        self.url
+            .to_file_path()
+            .ok()
+            .and_then(|p| p.file_name().map(|n| n.to_string_lossy().into_owned()))
+            .unwrap_or_default()
+    }
 
-    pub fn more(&mut self) {
+    // Both return whether the batch changed.
+    pub fn more(&mut self) -> bool {
+        let batch = self.batch;
         self.batch = (self.items.len() / self.index.max(1) as usize)
             .min(self.batch as usize + 1) as u8;
         self.changed = true;
+        self.batch != batch
     }
 
-    pub fn less(&mut self) {
+    pub fn less(&mut self) -> bool {
+        let batch = self.batch;
         self.batch = self.batch.saturating_sub(2) + 1;
         self.changed = true;
+        self.batch != batch
+    }
+
+    pub fn toggle_direction(&mut self) {
+        self.direction = self.direction.flip();
+        let rtl = self.direction == Direction::Rtl;
+        state::set(&self.root, RTL, rtl);
+    }
+
+    pub fn direction(&self) -> Direction { self.direction }
+
+    fn visible(&mut self) -> impl Iterator<Item = &mut Picture> {
+        let shown = self.shown();
+        self.items.values_mut().skip(shown.start).take(shown.len())
+    }
+
+    // Pages on screen, the current view or whatever part of the strip is
+    // scrolled into the window.
+    fn shown(&self) -> Range<usize> {
+        match self.mode {
+            Mode::Strip => within(&self.layout, self.scroll, self.viewport.1),
+            _ => self.view(),
+        }
     }
 
-    pub fn toggle_direction(&mut self) { self.direction ^= true; }
+    // Pages of the current view, empty while there are none.
+    fn view(&self) -> Range<usize> {
+        self.views.get(self.index).cloned().unwrap_or(0..0)
+    }
 
-    // TODO: Severe optimisation required.
-    pub fn folder_stats(
+    // Groups the pages into views, the index keeps pointing at a valid one.
+    fn split(&mut self) {
+        let sizes: Vec<_> = self.items.values().map(|p| (p.w, p.h)).collect();
+        self.views = views(&sizes, self.mode, self.batch as usize);
+        if self.end && !self.views.is_empty() {
+            // The strip is scrolled to its end once laid out.
+            self.end = false;
+            self.index = usize::MAX;
+            self.scroll = f64::MAX;
+            self.target = f64::MAX;
+        }
+        self.index = self.index.min(self.views.len().saturating_sub(1));
+    }
+
+    // Moves to the view holding the given page.
+    fn show(
         &mut self,
-        w: f64,
-        h: f64,
+        page: usize,
     ) {
-        self.stats = (1..=self.batch as usize)
-            .map(|n| {
-                (
-                    self.items.len() / n,
-                    self.items
-                        .values()
-                        .chunks(self.batch as usize)
-                        .into_iter()
-                        .nth(self.index)
-                        .unwrap()
-                        .chunks(n)
-                        .into_iter()
-                        .map(|c| c.fold((0, 0), |a, b| (a.0 + b.w, a.1.max(b.h))))
-                        .fold((0, 0), |a, b| (a.0.max(b.0), a.1 + b.1)),
-                )
-            })
-            .map(|(a, b)| {
-                let x = w / b.0 as f64;
-                let y = h / b.1 as f64;
-                (a, b, (x - y).abs())
-            })
-            .min_by(|a, b| {
-                a.2.partial_cmp(&b.2).unwrap_or(std::cmp::Ordering::Less)
-            })
-            .unwrap();
+        if let Some(n) = self.views.iter().position(|v| v.contains(&page)) {
+            self.index = n;
+            self.jump = Some(page);
+            self.home();
+        }
+    }
+
+    // Top of the view, on the side reading starts from.
+    fn home(&mut self) {
+        self.pan = match self.direction {
+            Direction::Ltr => [f64::MAX, f64::MAX],
+            Direction::Rtl => [f64::MIN, f64::MAX],
+        };
+    }
+
+    pub fn set_fit(
+        &mut self,
+        fit: Fit,
+    ) {
+        self.fit = fit;
+        self.home();
+    }
+
+    pub fn fit(&self) -> Fit { self.fit }
+
+    // Scales by `factor` keeping the page under `at` in place, false once
+    // the zoom is at its limit.
+    pub fn zoom_at(
+        &mut self,
+        factor: f64,
+        at: [f64; 2],
+    ) -> bool {
+        if self.mode == Mode::Strip {
+            return false;
+        }
+        let zoom = (self.zoom * factor).max(MIN_ZOOM).min(MAX_ZOOM);
+        let k = zoom / self.zoom;
+        let center = [self.viewport.0 / 2., self.viewport.1 / 2.];
+        for i in 0..2 {
+            self.pan[i] = (at[i] - center[i]) * (1. - k) + self.pan[i] * k;
+        }
+        self.fit = Fit::Zoom(zoom);
+        self.zoom = zoom;
+        k != 1.
+    }
+
+    // False once the view is at that edge.
+    pub fn pan(
+        &mut self,
+        dx: f64,
+        dy: f64,
+    ) -> bool {
+        if self.mode == Mode::Strip {
+            return false;
+        }
+        let pan = self.pan;
+        self.pan[0] += dx;
+        self.pan[1] += dy;
+        self.clamp_pan();
+        self.pan != pan
+    }
+
+    // Centered while it fits, otherwise no edge comes past the window.
+    fn clamp_pan(&mut self) {
+        let b = bounds(&self.layout);
+        let window = [self.viewport.0, self.viewport.1];
+        for i in 0..2 {
+            let (start, end) = (b[i], b[i] + b[i + 2]);
+            self.pan[i] = match end - start > window[i] {
+                true => self.pan[i].max(window[i] - end).min(-start),
+                false => 0.,
+            };
+        }
+    }
+
+    pub fn toggle_animation(&mut self) { self.paused ^= true; }
+
+    pub fn step_frame(&mut self) {
+        self.paused = true;
+        self.visible().for_each(|p| p.step());
+    }
+
+    pub fn sort(&mut self) {
+        let (mode, reverse) = (self.sort, self.reverse);
+        self.items.sort_by(|_, a, _, b| match reverse {
+            true => mode.compare(b, a),
+            false => mode.compare(a, b),
+        });
+    }
+
+    pub fn set_sort(
+        &mut self,
+        mode: SortMode,
+    ) {
+        self.sort = mode;
+        self.sort();
+    }
+
+    pub fn next_sort(&mut self) { self.set_sort(self.sort.next()); }
+
+    pub fn reverse_sort(&mut self) {
+        self.reverse ^= true;
+        self.sort();
+    }
+
+    pub fn set_mode(
+        &mut self,
+        mode: Mode,
+    ) {
+        let first = self.view().start;
+        self.mode = mode;
+        self.split();
+        self.show(first);
+    }
+
+    pub fn mode(&self) -> Mode { self.mode }
+
+    pub fn toggle_spread(&mut self) {
+        self.set_mode(match self.mode {
+            Mode::Spread { .. } => Mode::Rows(None),
+            _ => Mode::Spread { cover: true },
+        });
+    }
+
+    pub fn toggle_strip(&mut self) {
+        self.set_mode(match self.mode {
+            Mode::Strip => Mode::Rows(None),
+            _ => Mode::Strip,
+        });
+    }
+
+    pub fn toggle_cover(&mut self) {
+        if let Mode::Spread { cover } = self.mode {
+            self.set_mode(Mode::Spread { cover: !cover });
+        }
+    }
+
+    // Pages of the current view placed within the viewport, in strip mode
+    // the whole folder with the scroll kept inside it.
+    fn place(
+        &mut self,
+        viewport: (f64, f64),
+    ) {
+        self.viewport = viewport;
+        let view = match self.mode {
+            Mode::Strip => 0..self.items.len(),
+            _ => self.view(),
+        };
+        let sizes: Vec<_> = self
+            .items
+            .values()
+            .skip(view.start)
+            .take(view.len())
+            .map(|pic| (pic.w, pic.h))
+            .collect();
+        let (rects, zoom) =
+            layout(&sizes, viewport, self.mode, self.direction, self.fit);
+        self.layout = rects;
+        self.zoom = zoom;
+        let jump = self.jump.take();
+        if self.mode != Mode::Strip {
+            self.clamp_pan();
+            return;
+        }
+        if let Some(rect) = jump.and_then(|page| self.layout.get(page)) {
+            self.scroll = rect[1];
+            self.target = rect[1];
+        }
+        let end = (self.length() - viewport.1).max(0.);
+        self.scroll = self.scroll.max(0.).min(end);
+        self.target = self.target.max(0.).min(end);
+        let top = within(&self.layout, self.scroll, 0.).start;
+        self.index = top.min(self.views.len().saturating_sub(1));
     }
 }
+
+fn page(pb: PathBuf) -> (Url, Picture) {
+    let meta = pb.metadata().ok();
+    (Url::from_directory_path(&pb).ok().unwrap(), Picture {
+        modified: meta.as_ref().and_then(|m| m.modified().ok()),
+        size: meta.map(|m| m.len()).unwrap_or_default(),
+        path: pb,
+        ..Picture::default()
+    })
+}
 impl Display for Folder {
     fn fmt(
         &self,
@@ -259,102 +952,69 @@ impl Display for Folder {
             4..5 => format!("{:.2}T", s).to_string(),
             _ => "".to_string(),
         };
-        write!(
-            f,
-            "Name: {},\nSize: {}",
-            self.url
-                .to_file_path()
-                .ok()
-                .unwrap()
-                .file_name()
-                .unwrap()
-                .to_str()
-                .unwrap(),
-            s2,
-        )
+        write!(f, "Name: {},\nSize: {}", self.name(), s2)
     }
 }
-impl Draw<'_> for Folder {
-    type Params = (f64, f64);
+impl<'a> Draw<'a> for Folder {
+    type Params = Option<&'a mut Glyphs>;
 
     fn draw(
         &self,
         c: Context,
         g: &mut GfxGraphics<Resources, CommandBuffer>,
-        dim: Self::Params,
+        mut glyphs: Self::Params,
     ) {
-        let z = self.stats;
-        self.items
-            .values()
-            .into_iter()
-            .chunks(self.batch as usize)
-            .into_iter()
-            .nth(self.index as usize)
-            .expect("Picking a chunk.")
-            .enumerate()
-            .fold((0., 0., 0., 0.), |(x, y, w, h), (n, p)| {
-                let scale = match (
-                    (z.1 .0 as f64).min(dim.0) / (z.1 .0 as f64).max(dim.0),
-                    (z.1 .1 as f64).min(dim.1) / (z.1 .1 as f64).max(dim.1),
-                ) {
-                    (s, t) if s < t => s,
-                    (s, t) if s > t => t,
-                    (a, _) => a,
-                };
-                let _gap = (dim.0 * (z.1 .0 as f64 / dim.0).ceil() -
-                    (z.1 .0 as f64)) *
-                    scale /
-                    2.;
-                p.draw(c, g, (scale, &((x * scale), y as f64 * scale)));
-                // dbg!(&(n, z.0, z.1, y, h, &scale));
-                (
-                    if (x + p.w as f64) < z.1 .0 as f64 {
-                        x + p.w as f64
-                    } else {
-                        0.
-                    },
-                    if (x + p.w as f64) < z.1 .0 as f64 {
-                        y
-                    } else {
-                        y + h
-                    },
-                    if (x + p.w as f64) > z.1 .0 as f64 {
-                        w + p.w as f64
-                    } else {
-                        0.
-                    },
-                    if z.0 > n {
-                        (h as f64).max(p.h as f64)
-                    } else {
-                        0.
-                    },
-                )
-            });
+        let shown = self.shown();
+        let (skip, [x, y]) = match self.mode {
+            Mode::Strip => (shown.start, [0., -self.scroll]),
+            _ => (0, self.pan),
+        };
+        let pages = self.items.values().skip(shown.start).take(shown.len());
+        for (pic, rect) in pages.zip(self.layout.iter().skip(skip)) {
+            let scale = rect[2] / pic.w.max(1) as f64;
+            let at = (rect[0] + x, rect[1] + y);
+            pic.draw(c, g, (scale, &at, glyphs.as_deref_mut()));
+        }
     }
 }
 impl<'a> Prepare<'a> for Folder {
     type Input = (&'a mut G2dTextureContext, f64, f64);
+    type Output = Result<()>;
 
     fn prepare(
         &mut self,
         params: Self::Input,
-    ) {
-        // TODO: check for changes
+    ) -> Self::Output {
+        if !self.changed {
+            self.watch_changes();
+        }
+        let mut read = self.opened();
         if self.changed {
-            self.read();
-            self.items
-                .values_mut()
-                .for_each(|pic| pic.prepare(params.0));
-            self.size = self
-                .items
-                .values()
-                .fold(0, |acc, Picture { size, .. }| acc + size);
-            self.maxdim = self
-                .items
-                .values()
-                .fold((0, 0), |acc, pic| (acc.0 + pic.w, acc.1 + pic.h));
+            read = read.and(self.read());
+            self.probe();
+            self.totals();
         }
-        self.folder_stats(params.1, params.2);
+        self.probed();
+        self.split();
+        self.upload(params.0);
+        self.place((params.1, params.2));
+        self.load_window();
+        self.evict();
         self.changed = false;
+        read
+    }
+}
+impl Update for Folder {
+    type Input = f64;
+
+    fn update(
+        &mut self,
+        dt: Self::Input,
+    ) {
+        if !self.paused {
+            self.visible().for_each(|p| p.update(dt));
+        }
+        let ease = (dt * SCROLL_EASE).min(1.);
+        self.scroll += (self.target - self.scroll) * ease;
     }
 }
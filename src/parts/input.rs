@@ -0,0 +1,45 @@
+// What the mouse does, apart from the keyboard bindings.
+
+// Wheel outside of the strip, which always scrolls.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Wheel {
+    #[default]
+    Page,
+    // Moves a view larger than the window up and down.
+    Scroll,
+}
+
+// Thirds of the window a click lands in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Left,
+    Center,
+    Right,
+}
+impl Zone {
+    pub fn at(
+        x: f64,
+        width: f64,
+    ) -> Self {
+        match x / width.max(1.) {
+            x if x < 1. / 3. => Self::Left,
+            x if x > 2. / 3. => Self::Right,
+            _ => Self::Center,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mouse {
+    pub wheel: Wheel,
+    // Left clicks page on the sides and toggle the UI in the center.
+    pub zones: bool,
+}
+impl Default for Mouse {
+    fn default() -> Self {
+        Self {
+            wheel: Wheel::default(),
+            zones: true,
+        }
+    }
+}
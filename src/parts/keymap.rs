@@ -0,0 +1,381 @@
+use config::Value;
+use piston_window::{keyboard::ModifierKey, Key};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+// Everything a key can do. Quit and fullscreen act on the window, the rest
+// goes through `App::dispatch`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Left,
+    Right,
+    Up,
+    Down,
+    NextPage,
+    PrevPage,
+    FirstPage,
+    LastPage,
+    // To the page given as the count, the first one without.
+    GoTo,
+    NextChapter,
+    PrevChapter,
+    More,
+    Less,
+    ToggleDirection,
+    ToggleSpread,
+    ToggleCover,
+    ToggleStrip,
+    NextFit,
+    FitOriginal,
+    ZoomIn,
+    ZoomOut,
+    PanLeft,
+    PanRight,
+    PanUp,
+    PanDown,
+    NextSort,
+    ReverseSort,
+    ToggleAnimation,
+    StepFrame,
+    ToggleUi,
+    Fullscreen,
+    Quit,
+}
+
+// Names used in the `[keys]` table of the config file, and the keys bound
+// when it doesn't mention the action.
+const ACTIONS: [(Action, &str, &[&str]); 32] = [
+    (Action::Left, "left", &["A", "Left"]),
+    (Action::Right, "right", &["D", "Right"]),
+    (Action::Up, "up", &["W", "Up"]),
+    (Action::Down, "down", &["S", "Down"]),
+    (Action::NextPage, "next_page", &["L"]),
+    (Action::PrevPage, "prev_page", &["H"]),
+    (Action::FirstPage, "first_page", &["G G"]),
+    (Action::LastPage, "last_page", &["shift+G"]),
+    (Action::GoTo, "go_to", &["G"]),
+    (Action::NextChapter, "next_chapter", &[
+        "PageDown",
+        "RightBracket",
+    ]),
+    (Action::PrevChapter, "prev_chapter", &[
+        "PageUp",
+        "LeftBracket",
+    ]),
+    (Action::More, "more", &["NumPadPlus"]),
+    (Action::Less, "less", &["NumPadMinus"]),
+    (Action::ToggleDirection, "toggle_direction", &["R"]),
+    (Action::ToggleSpread, "toggle_spread", &["B"]),
+    (Action::ToggleCover, "toggle_cover", &["C"]),
+    (Action::ToggleStrip, "toggle_strip", &["V"]),
+    (Action::NextFit, "next_fit", &["Z"]),
+    (Action::FitOriginal, "fit_original", &["D0"]),
+    (Action::ZoomIn, "zoom_in", &["Equals"]),
+    (Action::ZoomOut, "zoom_out", &["Minus"]),
+    (Action::PanLeft, "pan_left", &["NumPad4"]),
+    (Action::PanRight, "pan_right", &["NumPad6"]),
+    (Action::PanUp, "pan_up", &["NumPad8"]),
+    (Action::PanDown, "pan_down", &["NumPad2"]),
+    (Action::NextSort, "next_sort", &["O"]),
+    (Action::ReverseSort, "reverse_sort", &["P"]),
+    (Action::ToggleAnimation, "toggle_animation", &["Space"]),
+    (Action::StepFrame, "step_frame", &["Period"]),
+    (Action::ToggleUi, "toggle_ui", &["U"]),
+    (Action::Fullscreen, "fullscreen", &["F", "F12"]),
+    (Action::Quit, "quit", &["Q"]),
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(a, ..)| *a == self)
+            .map(|(_, name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    // Times a count runs the action. Only moving around repeats, for
+    // `GoTo` the count is the page and everything else runs once.
+    pub fn repeat(
+        self,
+        count: Option<usize>,
+    ) -> usize {
+        let moves = matches!(
+            self,
+            Self::Left |
+                Self::Right |
+                Self::Up |
+                Self::Down |
+                Self::NextPage |
+                Self::PrevPage |
+                Self::NextChapter |
+                Self::PrevChapter |
+                Self::More |
+                Self::Less |
+                Self::ZoomIn |
+                Self::ZoomOut |
+                Self::PanLeft |
+                Self::PanRight |
+                Self::PanUp |
+                Self::PanDown
+        );
+        match moves {
+            true => count.unwrap_or(1),
+            false => 1,
+        }
+    }
+}
+
+// A key along with the modifiers held, written `ctrl+shift+PageDown`. Keys
+// go by their piston names, a binding can be several chords apart by spaces.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub key:  Key,
+    pub mods: ModifierKey,
+}
+impl Chord {
+    // A key pressed on its own.
+    pub fn plain(key: Key) -> Self {
+        Self {
+            key,
+            mods: ModifierKey::NO_MODIFIER,
+        }
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut parts: Vec<_> = text.split('+').map(str::trim).collect();
+        let key = Value::from(parts.pop()?).try_into().ok()?;
+        let mut mods = ModifierKey::NO_MODIFIER;
+        for part in parts {
+            mods |= match part.to_lowercase().as_str() {
+                "ctrl" => ModifierKey::CTRL,
+                "shift" => ModifierKey::SHIFT,
+                "alt" => ModifierKey::ALT,
+                "super" | "gui" => ModifierKey::GUI,
+                _ => return None,
+            };
+        }
+        Some(Self { key, mods })
+    }
+
+    // Held on their own they are no part of a sequence.
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self.key,
+            Key::LCtrl |
+                Key::RCtrl |
+                Key::LShift |
+                Key::RShift |
+                Key::LAlt |
+                Key::RAlt |
+                Key::LGui |
+                Key::RGui
+        )
+    }
+}
+
+fn chords(text: &str) -> Option<Vec<Chord>> {
+    text.split_whitespace().map(Chord::parse).collect()
+}
+impl Display for Chord {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        let names = [
+            (ModifierKey::CTRL, "ctrl+"),
+            (ModifierKey::SHIFT, "shift+"),
+            (ModifierKey::ALT, "alt+"),
+            (ModifierKey::GUI, "super+"),
+        ];
+        for (m, name) in names.iter() {
+            if self.mods.contains(*m) {
+                f.write_str(name)?;
+            }
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap(HashMap<Vec<Chord>, Action>);
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = ACTIONS.iter().flat_map(|(action, _, keys)| {
+            keys.iter().filter_map(move |k| Some((chords(k)?, *action)))
+        });
+        Self(bindings.collect())
+    }
+}
+impl Keymap {
+    // The `[keys]` table of the config file maps action names to a key or a
+    // list of them, replacing the default keys of those actions. Whatever
+    // can't be bound is returned for the user to fix, a key bound twice
+    // keeps the first binding.
+    pub fn from_table(table: HashMap<String, Value>) -> (Self, Vec<String>) {
+        let mut problems = vec![];
+        let mut bound: Vec<(Action, Vec<Value>)> = vec![];
+        for (name, value) in table {
+            match ACTIONS.iter().find(|(_, n, _)| *n == name) {
+                Some((action, ..)) => {
+                    let keys = match value.clone().into_array() {
+                        Ok(keys) => keys,
+                        Err(_) => vec![value],
+                    };
+                    bound.push((*action, keys));
+                }
+                None => problems.push(format!("keys: no action {}", name)),
+            }
+        }
+        // Actions left to their defaults come last, the table takes priority.
+        bound.sort_by_key(|(a, _)| ACTIONS.iter().position(|(b, ..)| a == b));
+        let defaults: Vec<(Action, Vec<Value>)> = ACTIONS
+            .iter()
+            .filter(|(a, ..)| bound.iter().all(|(b, _)| a != b))
+            .map(|(a, _, keys)| (*a, keys.iter().map(|&k| k.into()).collect()))
+            .collect();
+        let mut map = HashMap::new();
+        for (action, keys) in bound.into_iter().chain(defaults) {
+            for key in keys {
+                let text = key.into_str().unwrap_or_default();
+                let chords = match chords(&text) {
+                    Some(chords) if !chords.is_empty() => chords,
+                    _ => {
+                        problems.push(format!(
+                            "keys: {} can't be bound to {}",
+                            text,
+                            action.name()
+                        ));
+                        continue;
+                    }
+                };
+                match map.get(&chords) {
+                    Some(&other) if other != action => problems.push(format!(
+                        "keys: {} is bound to both {} and {}",
+                        text,
+                        other.name(),
+                        action.name()
+                    )),
+                    _ => {
+                        map.insert(chords, action);
+                    }
+                }
+            }
+        }
+        (Self(map), problems)
+    }
+
+    pub fn get(
+        &self,
+        keys: &[Chord],
+    ) -> Option<Action> {
+        self.0.get(keys).copied()
+    }
+
+    // Whether some longer binding starts with these keys.
+    pub fn is_prefix(
+        &self,
+        keys: &[Chord],
+    ) -> bool {
+        self.0
+            .keys()
+            .any(|k| k.len() > keys.len() && k.starts_with(keys))
+    }
+}
+
+// The default bindings as the `[keys]` table of the config file.
+pub fn defaults() -> String {
+    ACTIONS
+        .iter()
+        .map(|(_, name, keys)| format!("{} = {:?}\n", name, keys))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(key: Key) -> Vec<Chord> { vec![Chord::plain(key)] }
+
+    fn table(entries: &[(&str, &[&str])]) -> HashMap<String, Value> {
+        entries
+            .iter()
+            .map(|(name, keys)| (name.to_string(), keys.to_vec().into()))
+            .collect()
+    }
+
+    #[test]
+    fn chords_parse_their_modifiers() {
+        let parsed = Chord::parse("ctrl+shift+G");
+        let mods = ModifierKey::CTRL | ModifierKey::SHIFT;
+        assert_eq!(parsed, Some(Chord { key: Key::G, mods }));
+        let parsed = Chord::parse("Super+PageDown").unwrap();
+        assert_eq!(parsed.mods, ModifierKey::GUI);
+        assert_eq!(parsed.to_string(), "super+PageDown");
+        assert_eq!(Chord::parse("meta+G"), None);
+        assert_eq!(Chord::parse("ctrl+"), None);
+    }
+
+    #[test]
+    fn lists_bind_every_key_and_replace_the_defaults() {
+        let (keymap, problems) =
+            Keymap::from_table(table(&[("next_page", &["J", "ctrl+N"])]));
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(keymap.get(&single(Key::J)), Some(Action::NextPage));
+        let ctrl_n = Chord::parse("ctrl+N").unwrap();
+        assert_eq!(keymap.get(&[ctrl_n]), Some(Action::NextPage));
+        assert_eq!(keymap.get(&single(Key::L)), None);
+        // Untouched actions keep theirs.
+        assert_eq!(keymap.get(&single(Key::H)), Some(Action::PrevPage));
+    }
+
+    #[test]
+    fn sequences_are_several_chords() {
+        let (keymap, _) =
+            Keymap::from_table(table(&[("first_page", &["Home", "G G"])]));
+        let gg = [single(Key::G), single(Key::G)].concat();
+        assert_eq!(keymap.get(&gg), Some(Action::FirstPage));
+        assert!(keymap.is_prefix(&single(Key::G)));
+        assert!(!keymap.is_prefix(&gg));
+    }
+
+    #[test]
+    fn unknown_actions_and_keys_are_reported() {
+        let (keymap, problems) = Keymap::from_table(table(&[
+            ("jump", &["J"]),
+            ("next_page", &["NoSuchKey"]),
+        ]));
+        assert_eq!(problems.len(), 2);
+        assert!(problems.contains(&"keys: no action jump".to_string()));
+        assert!(problems.contains(
+            &"keys: NoSuchKey can't be bound to next_page".to_string()
+        ));
+        assert_eq!(keymap.get(&single(Key::J)), None);
+    }
+
+    #[test]
+    fn the_table_wins_conflicts_with_the_defaults() {
+        let (keymap, problems) =
+            Keymap::from_table(table(&[("next_page", &["Right"])]));
+        assert_eq!(keymap.get(&single(Key::Right)), Some(Action::NextPage));
+        assert_eq!(problems, vec![
+            "keys: Right is bound to both next_page and right".to_string()
+        ]);
+        // The other default of `right` still works.
+        assert_eq!(keymap.get(&single(Key::D)), Some(Action::Right));
+    }
+
+    #[test]
+    fn conflicts_in_the_table_keep_the_first_action() {
+        let (keymap, problems) = Keymap::from_table(table(&[
+            ("prev_page", &["J"]),
+            ("next_page", &["J"]),
+        ]));
+        assert_eq!(keymap.get(&single(Key::J)), Some(Action::NextPage));
+        assert_eq!(problems, vec![
+            "keys: J is bound to both next_page and prev_page".to_string()
+        ]);
+    }
+}
@@ -0,0 +1,341 @@
+// Placement of the pages on screen, kept free of any drawing so it only
+// depends on page sizes and the viewport.
+use std::ops::Range;
+
+// Position and size in window pixels, the same `[x, y, w, h]` as
+// `graphics::rectangle` takes.
+pub type Rect = [f64; 4];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // Rows of the given number of pages, or as many per row as makes the
+    // pages largest when `None`.
+    Rows(Option<usize>),
+    // Two pages side by side like a printed book. Wide pages already are a
+    // spread and stand alone, so can the cover to keep the pairs aligned.
+    Spread { cover: bool },
+    // Every page in one vertical strip as wide as the window, for webtoons.
+    // The rects then cover the whole folder and get scrolled over.
+    Strip,
+}
+impl Default for Mode {
+    fn default() -> Self { Self::Rows(None) }
+}
+
+// How large the pages of a view are drawn. Zoom is a plain scale factor,
+// 1 being the pixel size of the page.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Fit {
+    #[default]
+    Page,
+    Width,
+    Height,
+    Original,
+    Zoom(f64),
+}
+impl Fit {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Page => Self::Width,
+            Self::Width => Self::Height,
+            Self::Height => Self::Original,
+            Self::Original | Self::Zoom(_) => Self::Page,
+        }
+    }
+}
+
+// Order of the pages within a row, manga reads right to left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+impl Direction {
+    pub fn flip(self) -> Self {
+        match self {
+            Self::Ltr => Self::Rtl,
+            Self::Rtl => Self::Ltr,
+        }
+    }
+}
+
+// Pages shown together, as ranges over all pages of the folder. Rows take
+// `batch` pages at a time.
+pub fn views(
+    sizes: &[(u32, u32)],
+    mode: Mode,
+    batch: usize,
+) -> Vec<Range<usize>> {
+    let n = sizes.len();
+    match mode {
+        Mode::Strip => (0..n).map(|i| i..i + 1).collect(),
+        Mode::Rows(_) => (0..n)
+            .step_by(batch.max(1))
+            .map(|s| s..(s + batch.max(1)).min(n))
+            .collect(),
+        Mode::Spread { cover } => {
+            let wide = |i: usize| sizes[i].0 > sizes[i].1;
+            let mut out = vec![];
+            let mut i = 0;
+            if cover && n > 0 {
+                out.push(0..1);
+                i = 1;
+            }
+            while i < n {
+                let end = match i + 1 < n && !wide(i) && !wide(i + 1) {
+                    true => i + 2,
+                    false => i + 1,
+                };
+                out.push(i..end);
+                i = end;
+            }
+            out
+        }
+    }
+}
+
+// One rect per page, in the order the sizes were given, and the scale
+// `fit` came to. Pages in a row are scaled to the same height, the whole
+// block is then scaled and centered, right to left mirrors it. Strips always
+// fill the width and report a scale of 1.
+pub fn layout(
+    sizes: &[(u32, u32)],
+    viewport: (f64, f64),
+    mode: Mode,
+    direction: Direction,
+    fit: Fit,
+) -> (Vec<Rect>, f64) {
+    if sizes.is_empty() {
+        return (vec![], 1.);
+    }
+    let sizes: Vec<_> = sizes
+        .iter()
+        .map(|&(w, h)| (w.max(1) as f64, h.max(1) as f64))
+        .collect();
+    let per_row = match mode {
+        Mode::Strip => return (strip(&sizes, viewport.0), 1.),
+        Mode::Rows(Some(n)) => n.max(1).min(sizes.len()),
+        Mode::Spread { .. } => sizes.len(),
+        Mode::Rows(None) => {
+            (1..=sizes.len())
+                .map(|n| (n, scale(&rows(&sizes, n), viewport).0))
+                .fold((1, 0.), |best, (n, scale)| match scale > best.1 {
+                    true => (n, scale),
+                    false => best,
+                })
+                .0
+        }
+    };
+    let rows = rows(&sizes, per_row);
+    let (page, (w, h)) = scale(&rows, viewport);
+    let scale = match fit {
+        Fit::Page => page,
+        Fit::Width => viewport.0 / w,
+        Fit::Height => viewport.1 / h,
+        Fit::Original => 1.,
+        Fit::Zoom(zoom) => zoom,
+    };
+    let mut y = (viewport.1 - h * scale) / 2.;
+    let mut out = Vec::with_capacity(sizes.len());
+    for row in &rows {
+        let mut x = (viewport.0 - row.width() * scale) / 2.;
+        let height = row.height * scale;
+        for &width in &row.widths {
+            out.push([x, y, width * scale, height]);
+            x += width * scale;
+        }
+        y += height;
+    }
+    if direction == Direction::Rtl {
+        out.iter_mut().for_each(|r| r[0] = viewport.0 - r[0] - r[2]);
+    }
+    (out, scale)
+}
+
+// Smallest rect holding all of them.
+pub fn bounds(rects: &[Rect]) -> Rect {
+    let (x0, y0, x1, y1) = rects.iter().fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(x0, y0, x1, y1), r| {
+            (
+                x0.min(r[0]),
+                y0.min(r[1]),
+                x1.max(r[0] + r[2]),
+                y1.max(r[1] + r[3]),
+            )
+        },
+    );
+    match rects.is_empty() {
+        true => [0.; 4],
+        false => [x0, y0, x1 - x0, y1 - y0],
+    }
+}
+
+// Pages overlapping the part of a strip from `top` down by `height`.
+pub fn within(
+    rects: &[Rect],
+    top: f64,
+    height: f64,
+) -> Range<usize> {
+    let start = rects
+        .iter()
+        .position(|r| r[1] + r[3] > top)
+        .unwrap_or(rects.len());
+    let end = rects
+        .iter()
+        .position(|r| r[1] >= top + height)
+        .unwrap_or(rects.len());
+    start..end.max(start)
+}
+
+fn strip(
+    sizes: &[(f64, f64)],
+    width: f64,
+) -> Vec<Rect> {
+    let mut y = 0.;
+    sizes
+        .iter()
+        .map(|(w, h)| {
+            let rect = [0., y, width, h * width / w];
+            y += rect[3];
+            rect
+        })
+        .collect()
+}
+
+// Widths of the pages once scaled to the row's height.
+struct Row {
+    widths: Vec<f64>,
+    height: f64,
+}
+impl Row {
+    fn width(&self) -> f64 { self.widths.iter().sum() }
+}
+
+fn rows(
+    sizes: &[(f64, f64)],
+    per_row: usize,
+) -> Vec<Row> {
+    sizes
+        .chunks(per_row)
+        .map(|chunk| {
+            let height = chunk.iter().map(|s| s.1).fold(0., f64::max);
+            Row {
+                widths: chunk.iter().map(|(w, h)| w * height / h).collect(),
+                height,
+            }
+        })
+        .collect()
+}
+
+// Scale that fits the rows into the viewport, with their unscaled size.
+fn scale(
+    rows: &[Row],
+    viewport: (f64, f64),
+) -> (f64, (f64, f64)) {
+    let w = rows.iter().map(Row::width).fold(0., f64::max);
+    let h = rows.iter().map(|r| r.height).sum::<f64>();
+    ((viewport.0 / w).min(viewport.1 / h), (w, h))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PORTRAIT: (u32, u32) = (100, 150);
+
+    #[test]
+    fn rows_batch_pages_with_a_short_last_one() {
+        let sizes = [PORTRAIT; 5];
+        assert_eq!(views(&sizes, Mode::Rows(None), 2), vec![0..2, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn spreads_pair_pages_after_the_cover() {
+        let sizes = [PORTRAIT; 5];
+        let cover = Mode::Spread { cover: true };
+        let paired = Mode::Spread { cover: false };
+        assert_eq!(views(&sizes, cover, 2), vec![0..1, 1..3, 3..5]);
+        assert_eq!(views(&sizes, paired, 2), vec![0..2, 2..4, 4..5]);
+    }
+
+    #[test]
+    fn wide_pages_stand_alone_in_spreads() {
+        let sizes = [PORTRAIT, (300, 150), PORTRAIT, PORTRAIT];
+        let paired = Mode::Spread { cover: false };
+        assert_eq!(views(&sizes, paired, 2), vec![0..1, 1..2, 2..4]);
+    }
+
+    #[test]
+    fn rows_stack_without_overlap_and_are_centered() {
+        let sizes = [(100, 100); 4];
+        let (rects, scale) = layout(
+            &sizes,
+            (600., 400.),
+            Mode::Rows(Some(2)),
+            Direction::Ltr,
+            Fit::Page,
+        );
+        assert_eq!(scale, 2.);
+        assert_eq!(rects, vec![
+            [100., 0., 200., 200.],
+            [300., 0., 200., 200.],
+            [100., 200., 200., 200.],
+            [300., 200., 200., 200.],
+        ]);
+        assert_eq!(bounds(&rects), [100., 0., 400., 400.]);
+    }
+
+    #[test]
+    fn right_to_left_mirrors_the_rows() {
+        let sizes = [(100, 100); 2];
+        let (rects, _) = layout(
+            &sizes,
+            (600., 400.),
+            Mode::Rows(Some(2)),
+            Direction::Rtl,
+            Fit::Page,
+        );
+        assert_eq!(rects, vec![[300., 50., 300., 300.], [0., 50., 300., 300.]]);
+    }
+
+    #[test]
+    fn fits_scale_the_block() {
+        let sizes = [(100, 200)];
+        let place = |fit| {
+            layout(
+                &sizes,
+                (400., 300.),
+                Mode::Rows(Some(1)),
+                Direction::Ltr,
+                fit,
+            )
+        };
+        assert_eq!(place(Fit::Page).1, 1.5);
+        assert_eq!(place(Fit::Width).1, 4.);
+        assert_eq!(place(Fit::Height).1, 1.5);
+        assert_eq!(place(Fit::Zoom(0.5)).1, 0.5);
+        let (rects, scale) = place(Fit::Original);
+        assert_eq!(scale, 1.);
+        assert_eq!(rects, vec![[150., 50., 100., 200.]]);
+    }
+
+    #[test]
+    fn strips_fill_the_width() {
+        let sizes = [(100, 200), (200, 100)];
+        let (rects, scale) =
+            layout(&sizes, (400., 300.), Mode::Strip, Direction::Ltr, Fit::Page);
+        assert_eq!(scale, 1.);
+        assert_eq!(rects, vec![[0., 0., 400., 800.], [0., 800., 400., 200.]]);
+    }
+
+    #[test]
+    fn within_finds_the_pages_in_view() {
+        let rects = [[0., 0., 400., 800.], [0., 800., 400., 200.]];
+        assert_eq!(within(&rects, 0., 100.), 0..1);
+        assert_eq!(within(&rects, 700., 200.), 0..2);
+        assert_eq!(within(&rects, 900., 100.), 1..2);
+        assert_eq!(within(&rects, 1000., 100.), 2..2);
+    }
+}
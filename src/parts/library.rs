@@ -0,0 +1,186 @@
+use super::{
+    archive::is_archive,
+    detect::{extension, Filter},
+    sort::natural_cmp,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Series,
+    Volume,
+    Chapter,
+}
+
+// A chapter is a directory with pages in it or an archive, anything above it
+// is a volume when it only holds chapters and a series otherwise.
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub name:     String,
+    pub path:     PathBuf,
+    pub level:    Level,
+    pub children: Vec<Node>,
+}
+impl Node {
+    pub fn scan(
+        path: &Path,
+        filter: &Filter,
+    ) -> Option<Self> {
+        let name = file_name(path);
+        if path.is_file() {
+            return is_archive(&extension(path)).then(|| Self {
+                name,
+                path: path.to_path_buf(),
+                level: Level::Chapter,
+                children: vec![],
+            });
+        }
+        let mut entries: Vec<PathBuf> = path
+            .read_dir()
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect();
+        entries.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+        let pages = entries
+            .iter()
+            .any(|p| p.is_file() && filter.path(p).is_ok());
+        // Symlinked directories are skipped so loops can't recurse forever.
+        let children: Vec<Self> = entries
+            .iter()
+            .filter(|p| {
+                !p.symlink_metadata()
+                    .map(|m| m.file_type().is_symlink() && p.is_dir())
+                    .unwrap_or(true)
+            })
+            .filter_map(|p| Self::scan(p, filter))
+            .collect();
+        let level = match (pages, children.is_empty()) {
+            (true, _) => Level::Chapter,
+            (false, true) => return None,
+            _ if children.iter().all(|c| c.level == Level::Chapter) => {
+                Level::Volume
+            }
+            _ => Level::Series,
+        };
+        Some(Self {
+            name,
+            path: path.to_path_buf(),
+            level,
+            children,
+        })
+    }
+
+    pub fn chapters(
+        &self,
+        out: &mut Vec<PathBuf>,
+    ) {
+        if self.level == Level::Chapter {
+            out.push(self.path.clone());
+        }
+        self.children.iter().for_each(|c| c.chapters(out));
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Library {
+    pub root: Node,
+    chapters: Vec<PathBuf>,
+    current:  usize,
+}
+impl Library {
+    pub fn scan(
+        path: &Path,
+        filter: &Filter,
+    ) -> Option<Self> {
+        let mut root = Node::scan(path, filter)?;
+        if root.level == Level::Volume {
+            root.level = Level::Series;
+        }
+        let mut chapters = vec![];
+        root.chapters(&mut chapters);
+        Some(Self {
+            root,
+            chapters,
+            current: 0,
+        })
+    }
+
+    // The chapters next to `path` in its parent directory, used when a single
+    // chapter is opened so reading can continue past its last page.
+    pub fn siblings(
+        path: &Path,
+        filter: &Filter,
+    ) -> Option<Self> {
+        let path = path.canonicalize().ok()?;
+        let parent = path.parent()?;
+        let mut chapters: Vec<PathBuf> = parent
+            .read_dir()
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_chapter(p, filter))
+            .collect();
+        chapters.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+        let current = chapters.iter().position(|p| *p == path)?;
+        let children = chapters
+            .iter()
+            .map(|p| Node {
+                name:     file_name(p),
+                path:     p.clone(),
+                level:    Level::Chapter,
+                children: vec![],
+            })
+            .collect();
+        Some(Self {
+            root: Node {
+                name: file_name(parent),
+                path: parent.to_path_buf(),
+                level: Level::Volume,
+                children,
+            },
+            chapters,
+            current,
+        })
+    }
+
+    pub fn chapters(&self) -> &[PathBuf] { &self.chapters }
+
+    pub fn chapter(&self) -> &Path { &self.chapters[self.current] }
+
+    pub fn next_chapter(&mut self) -> Option<&Path> {
+        if self.current + 1 >= self.chapters.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.chapter())
+    }
+
+    pub fn prev_chapter(&mut self) -> Option<&Path> {
+        self.current = self.current.checked_sub(1)?;
+        Some(self.chapter())
+    }
+}
+
+fn is_chapter(
+    path: &Path,
+    filter: &Filter,
+) -> bool {
+    match path.is_dir() {
+        true => path
+            .read_dir()
+            .map(|dir| {
+                dir.filter_map(|e| e.ok())
+                    .map(|e| e.path())
+                    .any(|p| p.is_file() && filter.path(&p).is_ok())
+            })
+            .unwrap_or_default(),
+        false => is_archive(&extension(path)),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
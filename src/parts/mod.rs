@@ -1,16 +1,28 @@
 pub mod app;
+pub mod archive;
 pub mod common;
+pub mod decode;
+pub mod detect;
+pub mod error;
 pub mod folder;
+pub mod input;
+pub mod keymap;
+pub mod layout;
+pub mod library;
 pub mod picture;
+pub mod sequence;
+pub mod settings;
+pub mod sort;
+pub mod state;
 pub mod ui;
+pub mod watch;
+pub mod worker;
 
-pub use self::{app::App, common::*, folder::Folder, picture::Picture};
-
-const EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "bmp", "png"];
-fn contains(s: &str) -> bool {
-    let test = EXTENSIONS.iter().position(|&r| r == s).unwrap_or(999);
-    match test {
-        0..4 => true,
-        _ => false,
-    }
-}
+pub use self::{
+    app::App,
+    common::*,
+    error::Error,
+    folder::Folder,
+    picture::Picture,
+    sort::SortMode,
+};
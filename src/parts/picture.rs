@@ -1,32 +1,247 @@
-use super::common::{Draw, Prepare};
+use super::{
+    archive::Archive,
+    common::{Draw, Prepare, Update},
+    decode::Limits,
+    detect::Format,
+    error::{Error, Result},
+    worker::{Decoded, Job},
+};
+use gfx::Factory;
 use gfx_device_gl::{CommandBuffer, Resources};
-use gfx_graphics::{Flip, GfxGraphics, ImageSize, Texture, TextureSettings};
-use graphics::{image, Context, Transformed};
-use piston_window::texture;
-use std::path::PathBuf;
+use gfx_graphics::{GfxGraphics, ImageSize, Texture, TextureSettings};
+use graphics::{math::Matrix2d, rectangle, text::Text, Context, Transformed};
+use image::{imageops::crop_imm, RgbaImage};
+use piston_window::{texture, G2dTextureContext, Glyphs};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+const PLACEHOLDER: [f32; 4] = [0.15, 0.15, 0.15, 1.];
+const BROKEN: [f32; 4] = [0.3, 0.05, 0.05, 1.];
+// Pages that failed before their size was known get a portrait sized tile.
+const BROKEN_SIZE: (u32, u32) = (800, 1200);
+const BROKEN_TEXT: u32 = 32;
+// What GL guarantees at the very least.
+const FALLBACK_TILE: u32 = 1024;
 
 #[derive(Clone, Debug)]
 pub struct Picture {
-    pub path: PathBuf,
-    pub w:    u32,
-    pub h:    u32,
-    pub size: u64,
-    pub tex:  Option<Texture<Resources>>,
+    pub path:     PathBuf,
+    pub w:        u32,
+    pub h:        u32,
+    pub size:     u64,
+    pub modified: Option<SystemTime>,
+    // Name of the page inside an archive, `path` then points at the archive.
+    pub entry:    Option<String>,
+    pub format:   Option<Format>,
+    // Bits per pixel and EXIF tags, known once the page was decoded.
+    pub depth:    Option<u16>,
+    pub exif:     Vec<(String, String)>,
+    // Set once decoding was attempted, whether or not it worked.
+    pub loaded:   bool,
+    // Queued on the worker pool, a placeholder is drawn meanwhile.
+    pub pending:  bool,
+    // Its size is being read from the header on the worker pool.
+    pub probing:  bool,
+    // Tells the page apart from an earlier one at the same path, results of
+    // jobs queued for that one are dropped.
+    pub version:  u64,
+    // Why the page couldn't be shown, drawn over its placeholder.
+    pub error:    Option<String>,
+    // Last time the page was in or near view, for evicting its textures.
+    pub seen:     Option<Instant>,
+    pub tex:      Option<Tiles>,
+    // Only filled for animations, `tex` then holds the first frame.
+    pub frames:   Vec<(Tiles, Duration)>,
+    pub frame:    usize,
+    pub elapsed:  f64,
 }
 impl Default for Picture {
     fn default() -> Self {
         let pb = PathBuf::from(".");
         Self {
-            path: pb.clone(),
-            w:    0,
-            h:    0,
-            size: pb.metadata().unwrap().len(),
-            tex:  None,
+            path:     pb.clone(),
+            w:        0,
+            h:        0,
+            size:     pb.metadata().map(|m| m.len()).unwrap_or_default(),
+            modified: None,
+            entry:    None,
+            format:   None,
+            depth:    None,
+            exif:     vec![],
+            loaded:   false,
+            pending:  false,
+            probing:  false,
+            version:  0,
+            error:    None,
+            seen:     None,
+            tex:      None,
+            frames:   vec![],
+            frame:    0,
+            elapsed:  0.,
+        }
+    }
+}
+impl Picture {
+    pub fn name(&self) -> String {
+        if let Some(entry) = &self.entry {
+            return entry.clone();
+        }
+        self.path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn step(&mut self) {
+        if !self.frames.is_empty() {
+            self.frame = (self.frame + 1) % self.frames.len();
+            self.elapsed = 0.;
+        }
+    }
+
+    // Bytes held by the textures, every frame counts.
+    pub fn memory(&self) -> u64 {
+        match self.frames.is_empty() {
+            true => self.tex.as_ref().map(Tiles::memory).unwrap_or_default(),
+            false => self.frames.iter().map(|(t, _)| t.memory()).sum(),
+        }
+    }
+
+    // Keeps the size so layout doesn't jump, the page is decoded again once
+    // it comes back into view.
+    pub fn unload(&mut self) {
+        self.tex = None;
+        self.frames.clear();
+        self.frame = 0;
+        self.elapsed = 0.;
+        self.loaded = false;
+        self.error = None;
+    }
+
+    pub fn job(
+        &self,
+        archive: &Option<Archive>,
+        limits: Limits,
+    ) -> Job {
+        Job {
+            archive: archive.clone(),
+            path: self.path.clone(),
+            entry: self.entry.clone(),
+            format: self.format,
+            limits,
+            version: self.version,
+        }
+    }
+
+    // The only part of loading a page that has to run on the render thread.
+    pub fn upload(
+        &mut self,
+        ctx: &mut G2dTextureContext,
+        decoded: Decoded,
+    ) -> Result<()> {
+        self.loaded = true;
+        self.pending = false;
+        self.frame = 0;
+        self.elapsed = 0.;
+        self.tex = None;
+        self.frames.clear();
+        let page = match decoded {
+            Ok(page) => page,
+            Err(e) => return Err(self.fail(e)),
+        };
+        if let Some((img, _)) = page.frames.first() {
+            (self.w, self.h) = img.dimensions();
+        }
+        self.depth = page.depth;
+        self.exif = page.exif;
+        let mut textures = page
+            .frames
+            .iter()
+            .map(|(img, delay)| Tiles::new(ctx, img).map(|t| (t, *delay)))
+            .collect::<Option<Vec<_>>>()
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| self.fail(Error::Upload))?;
+        self.tex = textures.first().map(|(t, _)| t.clone());
+        if textures.len() < 2 {
+            textures.clear();
+        }
+        self.frames = textures;
+        self.error = None;
+        Ok(())
+    }
+
+    // Keeps the message for the placeholder and hands the error back.
+    fn fail(
+        &mut self,
+        e: Error,
+    ) -> Error {
+        if (self.w, self.h) == (0, 0) {
+            (self.w, self.h) = BROKEN_SIZE;
+        }
+        self.error = Some(e.to_string());
+        e
+    }
+}
+
+// A bitmap cut into textures no larger than the GPU takes, each with its
+// offset in pixels, so strips taller than the texture limit still show.
+#[derive(Clone, Debug)]
+pub struct Tiles(pub Vec<(Texture<Resources>, [f64; 2])>);
+impl Tiles {
+    pub fn new(
+        ctx: &mut G2dTextureContext,
+        img: &RgbaImage,
+    ) -> Option<Self> {
+        let settings = TextureSettings::new().filter(texture::Filter::Nearest);
+        let max = ctx.factory.get_capabilities().max_texture_size as u32;
+        let max = if max == 0 { FALLBACK_TILE } else { max };
+        let (w, h) = img.dimensions();
+        if w <= max && h <= max {
+            let tex = Texture::from_image(ctx, img, &settings).ok()?;
+            return Some(Self(vec![(tex, [0., 0.])]));
+        }
+        (0..h)
+            .step_by(max as usize)
+            .flat_map(|y| (0..w).step_by(max as usize).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let tile = crop_imm(img, x, y, max.min(w - x), max.min(h - y))
+                    .to_image();
+                Texture::from_image(ctx, &tile, &settings)
+                    .ok()
+                    .map(|t| (t, [x as f64, y as f64]))
+            })
+            .collect::<Option<_>>()
+            .map(Self)
+    }
+
+    pub fn memory(&self) -> u64 {
+        self.0
+            .iter()
+            .map(|(t, _)| {
+                let (w, h) = t.get_size();
+                w as u64 * h as u64 * 4
+            })
+            .sum()
+    }
+}
+impl Draw<'_> for Tiles {
+    type Params = Matrix2d;
+
+    fn draw(
+        &self,
+        _: Context,
+        g: &mut GfxGraphics<Resources, CommandBuffer>,
+        transform: Self::Params,
+    ) {
+        for (tex, [x, y]) in &self.0 {
+            graphics::image(tex, transform.trans(*x, *y), g);
         }
     }
 }
 impl<'a> Draw<'a> for Picture {
-    type Params = (f64, &'a (f64, f64));
+    type Params = (f64, &'a (f64, f64), Option<&'a mut Glyphs>);
 
     fn draw(
         &self,
@@ -38,28 +253,68 @@ impl<'a> Draw<'a> for Picture {
             .trans(params.1 .0, params.1 .1)
             .transform
             .append_transform(graphics::math::scale(params.0, params.0));
-        if let Some(texture) = &self.tex {
-            image(texture, transform, g);
+        let texture = self.frames.get(self.frame).map(|(t, _)| t);
+        match texture.or(self.tex.as_ref()) {
+            Some(tiles) => tiles.draw(c, g, transform),
+            None if self.pending => rectangle(
+                PLACEHOLDER,
+                [0., 0., self.w as f64, self.h as f64],
+                transform,
+                g,
+            ),
+            None => {
+                let error = match &self.error {
+                    Some(error) => error,
+                    None => return,
+                };
+                let rect = [0., 0., self.w as f64, self.h as f64];
+                rectangle(BROKEN, rect, transform, g);
+                if let Some(glyphs) = params.2 {
+                    let text = Text::new_color([1.; 4], BROKEN_TEXT);
+                    let size = BROKEN_TEXT as f64;
+                    for (n, line) in
+                        [self.name(), error.clone()].iter().enumerate()
+                    {
+                        let at = transform.trans(size, size * (n + 2) as f64);
+                        text.draw(line, glyphs, &c.draw_state, at, g).ok();
+                    }
+                }
+            }
         }
     }
 }
+// Decodes right away on the calling thread, the same job a Folder queues on
+// its pool.
 impl<'a> Prepare<'a> for Picture {
+    type Input = (&'a mut G2dTextureContext, &'a Option<Archive>, Limits);
+    type Output = Result<()>;
+
     fn prepare(
         &mut self,
-        ctx: Self::Input,
+        params: Self::Input,
+    ) -> Self::Output {
+        let decoded = self.job(params.1, params.2).run();
+        self.upload(params.0, decoded)
+    }
+}
+impl Update for Picture {
+    type Input = f64;
+
+    fn update(
+        &mut self,
+        dt: Self::Input,
     ) {
-        self.tex = match Texture::from_path(
-            ctx,
-            &self.path,
-            Flip::None,
-            &TextureSettings::new().filter(texture::Filter::Nearest),
-        ) {
-            Ok(t) => Some(t),
-            Err(_) => None,
-        };
-        if let Some(t) = &self.tex {
-            (self.w, self.h) = t.get_size();
+        if self.frames.is_empty() {
+            return;
+        }
+        self.elapsed += dt;
+        while let Some((_, delay)) = self.frames.get(self.frame) {
+            let delay = delay.as_secs_f64();
+            if self.elapsed < delay {
+                break;
+            }
+            self.elapsed -= delay;
+            self.frame = (self.frame + 1) % self.frames.len();
         }
-        self.size = self.path.metadata().unwrap().len();
     }
 }
@@ -0,0 +1,228 @@
+use super::{
+    common::Draw,
+    keymap::{Action, Chord, Keymap},
+    ui,
+};
+use gfx_device_gl::{CommandBuffer, Resources};
+use gfx_graphics::GfxGraphics;
+use graphics::{Context, Transformed};
+use piston_window::{keyboard::ModifierKey, Glyphs, Key};
+use std::time::{Duration, Instant};
+
+// A binding that is also the start of a longer one fires once no key came
+// for this long.
+const TIMEOUT: Duration = Duration::from_millis(1000);
+// More pages than any folder has, further digits are ignored.
+const MAX_COUNT: usize = 99_999;
+
+// Keys typed so far, vim style: a count followed by the chords of a
+// binding, `42g` or `gg`.
+#[derive(Clone, Debug, Default)]
+pub struct Sequence {
+    count: Option<usize>,
+    keys:  Vec<Chord>,
+    last:  Option<Instant>,
+}
+impl Sequence {
+    // The action once the keys complete a binding, with the count typed
+    // before it. Keys that lead nowhere drop the whole sequence.
+    pub fn feed(
+        &mut self,
+        chord: Chord,
+        keymap: &Keymap,
+    ) -> Option<(Action, Option<usize>)> {
+        if chord.is_modifier() {
+            return None;
+        }
+        self.last = Some(Instant::now());
+        if let Some(digit) = digit(chord) {
+            // A leading zero is a key of its own, as in vim.
+            if self.keys.is_empty() && (digit > 0 || self.count.is_some()) {
+                let count = self.count.unwrap_or_default();
+                let count = count.saturating_mul(10).saturating_add(digit);
+                self.count = Some(count.min(MAX_COUNT));
+                return None;
+            }
+        }
+        self.keys.push(chord);
+        let longer = keymap.is_prefix(&self.keys);
+        match keymap.get(&self.keys) {
+            // A count can only be followed by a single binding, so it
+            // doesn't wait for a longer one.
+            Some(action) if !longer || self.count.is_some() => {
+                let count = self.count;
+                self.clear();
+                Some((action, count))
+            }
+            _ if longer => None,
+            _ => {
+                self.clear();
+                None
+            }
+        }
+    }
+
+    // Fires a pending binding once the timeout has passed.
+    pub fn tick(
+        &mut self,
+        keymap: &Keymap,
+    ) -> Option<(Action, Option<usize>)> {
+        match self.last {
+            Some(last) if last.elapsed() > TIMEOUT => {
+                let action = keymap.get(&self.keys);
+                let count = self.count;
+                self.clear();
+                action.map(|a| (a, count))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.count.is_some() || !self.keys.is_empty()
+    }
+
+    fn clear(&mut self) { *self = Self::default(); }
+
+    fn text(&self) -> String {
+        let keys = self.keys.iter().map(|k| k.to_string());
+        self.count
+            .map(|c| c.to_string())
+            .into_iter()
+            .chain(keys)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+impl<'a> Draw<'a> for Sequence {
+    type Params = &'a mut Glyphs;
+
+    // Bottom left corner, for as long as the sequence is pending.
+    fn draw(
+        &self,
+        c: Context,
+        g: &mut GfxGraphics<Resources, CommandBuffer>,
+        glyphs: Self::Params,
+    ) {
+        if !self.pending() {
+            return;
+        }
+        let y = c.get_view_size()[1] - ui::line_height();
+        ui::label(&self.text(), c.trans(0., y), g, glyphs);
+    }
+}
+
+fn digit(chord: Chord) -> Option<usize> {
+    if chord.mods != ModifierKey::NO_MODIFIER {
+        return None;
+    }
+    let digits = [
+        Key::D0,
+        Key::D1,
+        Key::D2,
+        Key::D3,
+        Key::D4,
+        Key::D5,
+        Key::D6,
+        Key::D7,
+        Key::D8,
+        Key::D9,
+    ];
+    digits.iter().position(|&k| k == chord.key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What the last of the keys fired.
+    fn feed(
+        sequence: &mut Sequence,
+        keys: &[Key],
+    ) -> Option<(Action, Option<usize>)> {
+        let keymap = Keymap::default();
+        keys.iter()
+            .fold(None, |_, &k| sequence.feed(Chord::plain(k), &keymap))
+    }
+
+    #[test]
+    fn counts_repeat_the_binding() {
+        let mut sequence = Sequence::default();
+        let fired = feed(&mut sequence, &[Key::D5, Key::L]);
+        assert_eq!(fired, Some((Action::NextPage, Some(5))));
+        assert_eq!(Action::NextPage.repeat(Some(5)), 5);
+        assert_eq!(Action::ToggleDirection.repeat(Some(5)), 1);
+        assert!(!sequence.pending());
+    }
+
+    #[test]
+    fn counts_are_the_page_to_go_to() {
+        let mut sequence = Sequence::default();
+        let fired = feed(&mut sequence, &[Key::D4, Key::D2, Key::G]);
+        assert_eq!(fired, Some((Action::GoTo, Some(42))));
+        assert_eq!(Action::GoTo.repeat(Some(42)), 1);
+    }
+
+    #[test]
+    fn double_g_is_the_first_page() {
+        let mut sequence = Sequence::default();
+        assert_eq!(feed(&mut sequence, &[Key::G]), None);
+        assert!(sequence.pending());
+        let fired = feed(&mut sequence, &[Key::G]);
+        assert_eq!(fired, Some((Action::FirstPage, None)));
+    }
+
+    #[test]
+    fn a_lone_g_fires_on_timeout() {
+        let keymap = Keymap::default();
+        let mut sequence = Sequence::default();
+        assert_eq!(feed(&mut sequence, &[Key::G]), None);
+        assert_eq!(sequence.tick(&keymap), None);
+        sequence.last = Some(Instant::now() - TIMEOUT - Duration::from_millis(1));
+        assert_eq!(sequence.tick(&keymap), Some((Action::GoTo, None)));
+        assert!(!sequence.pending());
+    }
+
+    #[test]
+    fn a_leading_zero_is_a_key() {
+        let mut sequence = Sequence::default();
+        let fired = feed(&mut sequence, &[Key::D0]);
+        assert_eq!(fired, Some((Action::FitOriginal, None)));
+        let fired = feed(&mut sequence, &[Key::D1, Key::D0, Key::L]);
+        assert_eq!(fired, Some((Action::NextPage, Some(10))));
+    }
+
+    #[test]
+    fn modifiers_alone_are_ignored() {
+        let keymap = Keymap::default();
+        let mut sequence = Sequence::default();
+        let shift = |key| Chord {
+            key,
+            mods: ModifierKey::SHIFT,
+        };
+        assert_eq!(sequence.feed(shift(Key::LShift), &keymap), None);
+        assert!(!sequence.pending());
+        let fired = sequence.feed(shift(Key::G), &keymap);
+        assert_eq!(fired, Some((Action::LastPage, None)));
+    }
+
+    #[test]
+    fn counts_are_capped() {
+        let mut sequence = Sequence::default();
+        let mut keys = vec![Key::D9; 25];
+        keys.push(Key::L);
+        let fired = feed(&mut sequence, &keys);
+        assert_eq!(fired, Some((Action::NextPage, Some(MAX_COUNT))));
+    }
+
+    #[test]
+    fn dead_ends_clear_the_sequence() {
+        let mut sequence = Sequence::default();
+        assert_eq!(feed(&mut sequence, &[Key::G, Key::L]), None);
+        assert!(!sequence.pending());
+        assert_eq!(feed(&mut sequence, &[Key::D3, Key::X]), None);
+        assert!(!sequence.pending());
+        let fired = feed(&mut sequence, &[Key::L]);
+        assert_eq!(fired, Some((Action::NextPage, None)));
+    }
+}
@@ -0,0 +1,264 @@
+use super::{
+    decode::Limits,
+    detect::Filter,
+    error::{Error, Result},
+    input::{Mouse, Wheel},
+    keymap::{self, Keymap},
+};
+use config::{Config, Environment, File, FileFormat};
+use piston_window::{OpenGL, WindowSettings};
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Debug,
+    ops::RangeInclusive,
+    path::PathBuf,
+    time::Duration,
+};
+
+// Every setting with its default, also printed by `--print-default-config`.
+// The default keys are only added to the printed file, bindings read from
+// the file and the environment then replace them per action.
+const DEFAULTS: &str = r#"# Settings of cb00.
+#
+# Read from $XDG_CONFIG_HOME/cb00/config.toml, or ~/.config/cb00/config.toml.
+# Any value can also be set from the environment as CB00_<TABLE>__<KEY>, for
+# example CB00_WINDOW__VSYNC=true.
+
+[window]
+fullscreen = false
+vsync = false
+# Keeps the cursor inside the window.
+capture = false
+# Escape closes the window.
+esc_exit = true
+transparent = true
+# Multisampling, one of 0, 1, 2, 4, 8 or 16.
+samples = 16
+# OpenGL version from "2.0" to "4.5".
+opengl = "4.5"
+# Updates and frames per second.
+ups = 30
+fps = 60
+
+[pages]
+# Pages decoded ahead of the current view.
+prefetch = 4
+# Megabytes of textures each folder keeps around.
+budget = 512
+# Formats by extension. An empty allow list shows all of them, the deny
+# list also skips files by their own extension.
+allow = []
+deny = []
+
+[limits]
+# Pages over any of these are refused: size in pixels, megabytes decoded
+# and seconds spent decoding.
+width = 16384
+height = 65535
+megabytes = 1024
+seconds = 10
+
+[mouse]
+# What the wheel does outside the vertical strip, "page" or "scroll".
+wheel = "page"
+# Left clicks on the sides of the window page, in the center they toggle
+# the UI. Dragging with the left or middle button moves the view either way.
+zones = true
+
+[keys]
+# Actions with a key or a list of them. Keys go by their piston names, with
+# any of ctrl+, shift+, alt+ and super+ in front. Several keys apart by
+# spaces make a sequence, digits typed before one are its count.
+"#;
+
+const SAMPLES: [i64; 6] = [0, 1, 2, 4, 8, 16];
+
+#[derive(Clone)]
+pub struct Settings {
+    pub fullscreen:  bool,
+    pub vsync:       bool,
+    pub capture:     bool,
+    pub esc_exit:    bool,
+    pub transparent: bool,
+    pub ups:         u64,
+    pub fps:         u64,
+    pub samples:     u8,
+    pub opengl:      OpenGL,
+    pub filter:      Filter,
+    pub prefetch:    usize,
+    // Bytes of textures each folder keeps around.
+    pub budget:      u64,
+    pub limits:      Limits,
+    pub mouse:       Mouse,
+    pub keys:        Keymap,
+    pub window:      WindowSettings,
+    //add new fields to Debug impl
+}
+impl Default for Settings {
+    fn default() -> Self {
+        let config = defaults().expect("default config");
+        Self::from_config(&config).expect("default config").0
+    }
+}
+impl Settings {
+    // Defaults, then the config file when there is one, then the
+    // environment. Keys that can't be bound are returned alongside, the
+    // rest of the settings apply either way.
+    pub fn load() -> Result<(Self, Vec<String>)> {
+        let mut config = defaults()?;
+        if let Some(file) = file() {
+            config
+                .merge(File::from(file).required(false))
+                .map_err(err)?;
+        }
+        config
+            .merge(Environment::with_prefix("CB00").separator("__"))
+            .map_err(err)?;
+        Self::from_config(&config)
+    }
+
+    fn from_config(config: &Config) -> Result<(Self, Vec<String>)> {
+        let flag = |key: &str| config.get_bool(key).map_err(err);
+        let int = |key: &str, range: RangeInclusive<i64>| {
+            let value = config.get_int(key).map_err(err)?;
+            match range.contains(&value) {
+                true => Ok(value),
+                false => Err(Error::Config(format!(
+                    "{} is {}, it has to be within {} and {}",
+                    key,
+                    value,
+                    range.start(),
+                    range.end()
+                ))),
+            }
+        };
+        let list = |key: &str| {
+            config
+                .get_array(key)
+                .map_err(err)?
+                .into_iter()
+                .map(|v| v.into_str().map_err(err))
+                .collect::<Result<Vec<_>>>()
+        };
+        let samples = int("window.samples", 0..=16)?;
+        if !SAMPLES.contains(&samples) {
+            return Err(Error::Config(format!(
+                "window.samples is {}, it has to be one of {:?}",
+                samples, SAMPLES
+            )));
+        }
+        let opengl = config.get_str("window.opengl").map_err(err)?;
+        let opengl = opengl
+            .parse::<OpenGL>()
+            .map_err(|e| Error::Config(format!("window.opengl: {}", e)))?;
+        let wheel = match config.get_str("mouse.wheel").map_err(err)?.as_str() {
+            "page" => Wheel::Page,
+            "scroll" => Wheel::Scroll,
+            other => {
+                return Err(Error::Config(format!(
+                    "mouse.wheel is {}, it has to be page or scroll",
+                    other
+                )))
+            }
+        };
+        let keys = match config.get_table("keys") {
+            Ok(table) => table,
+            Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+            Err(e) => return Err(err(e)),
+        };
+        let (keys, problems) = Keymap::from_table(keys);
+        let fullscreen = flag("window.fullscreen")?;
+        let vsync = flag("window.vsync")?;
+        let esc_exit = flag("window.esc_exit")?;
+        let transparent = flag("window.transparent")?;
+        let samples = samples as u8;
+        let mut window = WindowSettings::new("Reader", [1., 1.])
+            .fullscreen(fullscreen)
+            .exit_on_esc(esc_exit)
+            .samples(samples)
+            .vsync(vsync)
+            .graphics_api(opengl);
+        window.set_transparent(transparent);
+        let settings = Self {
+            fullscreen,
+            vsync,
+            capture: flag("window.capture")?,
+            esc_exit,
+            transparent,
+            ups: int("window.ups", 1..=1000)? as u64,
+            fps: int("window.fps", 1..=1000)? as u64,
+            samples,
+            opengl,
+            filter: Filter {
+                allow: list("pages.allow")?,
+                deny:  list("pages.deny")?,
+            },
+            prefetch: int("pages.prefetch", 0..=64)? as usize,
+            budget: int("pages.budget", 1..=1 << 20)? as u64 * (1 << 20),
+            limits: Limits {
+                width:  int("limits.width", 1..=1 << 20)? as u32,
+                height: int("limits.height", 1..=1 << 20)? as u32,
+                bytes:  int("limits.megabytes", 1..=1 << 20)? as u64 * (1 << 20),
+                time:   Duration::from_secs(
+                    int("limits.seconds", 1..=3600)? as u64
+                ),
+            },
+            mouse: Mouse {
+                wheel,
+                zones: flag("mouse.zones")?,
+            },
+            keys,
+            window,
+        };
+        Ok((settings, problems))
+    }
+}
+impl Debug for Settings {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_struct("Settings")
+            .field("fullscreen", &self.fullscreen)
+            .field("vsync", &self.vsync)
+            .field("capture", &self.capture)
+            .field("esc_exit", &self.esc_exit)
+            .field("transparent", &self.transparent)
+            .field("ups", &self.ups)
+            .field("fps", &self.fps)
+            .field("samples", &self.samples)
+            .field("opengl", &self.opengl)
+            .field("filter", &self.filter)
+            .field("prefetch", &self.prefetch)
+            .field("budget", &self.budget)
+            .field("limits", &self.limits)
+            .field("mouse", &self.mouse)
+            .field("keys", &self.keys)
+            .finish()
+    }
+}
+
+// The whole default config file, comments included.
+pub fn default_config() -> String {
+    format!("{}{}", DEFAULTS, keymap::defaults())
+}
+
+// $XDG_CONFIG_HOME/cb00/config.toml, or ~/.config when that isn't set.
+pub fn file() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home::home_dir().map(|h| h.join(".config")))
+        .map(|dir| dir.join("cb00").join("config.toml"))
+}
+
+fn defaults() -> Result<Config> {
+    let mut config = Config::new();
+    config
+        .merge(File::from_str(DEFAULTS, FileFormat::Toml))
+        .map_err(err)?;
+    Ok(config)
+}
+
+fn err(e: config::ConfigError) -> Error { Error::Config(e.to_string()) }
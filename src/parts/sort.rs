@@ -0,0 +1,133 @@
+use super::picture::Picture;
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortMode {
+    Name,
+    #[default]
+    Natural,
+    Modified,
+    Size,
+    Dimensions,
+}
+impl SortMode {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Name => Self::Natural,
+            Self::Natural => Self::Modified,
+            Self::Modified => Self::Size,
+            Self::Size => Self::Dimensions,
+            Self::Dimensions => Self::Name,
+        }
+    }
+
+    pub fn compare(
+        self,
+        a: &Picture,
+        b: &Picture,
+    ) -> Ordering {
+        let by_name =
+            || natural_cmp(&a.name(), &b.name()).then(a.name().cmp(&b.name()));
+        match self {
+            Self::Name => a.name().cmp(&b.name()),
+            Self::Natural => by_name(),
+            Self::Modified => a.modified.cmp(&b.modified).then_with(by_name),
+            Self::Size => a.size.cmp(&b.size).then_with(by_name),
+            Self::Dimensions => (a.w as u64 * a.h as u64)
+                .cmp(&(b.w as u64 * b.h as u64))
+                .then_with(by_name),
+        }
+    }
+}
+
+/// Compares strings treating runs of digits as numbers, so that `page2`
+/// sorts before `page10`. Letters are compared case-insensitively.
+pub fn natural_cmp(
+    a: &str,
+    b: &str,
+) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let (tx, ty) =
+                    (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                match tx
+                    .len()
+                    .cmp(&ty.len())
+                    .then_with(|| tx.cmp(ty))
+                    .then_with(|| x.len().cmp(&y.len()))
+                {
+                    Ordering::Equal => {}
+                    o => return o,
+                }
+            }
+            (Some(x), Some(y)) => {
+                match x.to_lowercase().cmp(y.to_lowercase()) {
+                    Ordering::Equal => {}
+                    o => return o,
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn digits(it: &mut Peekable<Chars>) -> String {
+    let mut s = String::new();
+    while let Some(c) = it.peek().filter(|c| c.is_ascii_digit()) {
+        s.push(*c);
+        it.next();
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(name: &str) -> Picture {
+        Picture {
+            entry: Some(name.to_string()),
+            ..Picture::default()
+        }
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(natural_cmp("page2", "page10"), Ordering::Less);
+        assert_eq!(natural_cmp("page10", "page2"), Ordering::Greater);
+        assert_eq!(natural_cmp("page10", "page10"), Ordering::Equal);
+        assert_eq!(natural_cmp("page", "page1"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros_only_break_ties() {
+        assert_eq!(natural_cmp("01", "2"), Ordering::Less);
+        assert_eq!(natural_cmp("010", "9"), Ordering::Greater);
+        assert_eq!(natural_cmp("1", "01"), Ordering::Less);
+        assert_eq!(natural_cmp("page01b", "page1a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn case_is_folded() {
+        assert_eq!(natural_cmp("Page2", "page10"), Ordering::Less);
+        assert_eq!(natural_cmp("PAGE", "page"), Ordering::Equal);
+        assert_eq!(natural_cmp("b", "A"), Ordering::Greater);
+    }
+
+    #[test]
+    fn name_sorts_bytes_and_natural_numbers() {
+        let (two, ten) = (page("page2.png"), page("page10.png"));
+        assert_eq!(SortMode::Name.compare(&two, &ten), Ordering::Greater);
+        assert_eq!(SortMode::Natural.compare(&two, &ten), Ordering::Less);
+        // Names equal but for case still get an order.
+        let (upper, lower) = (page("A.png"), page("a.png"));
+        assert_eq!(SortMode::Natural.compare(&upper, &lower), Ordering::Less);
+    }
+}
@@ -0,0 +1,49 @@
+use reqwest::Url;
+use std::{env, fs, path::PathBuf};
+
+// Per folder switches that outlive the session, kept as `<key> <url>` lines
+// in $XDG_DATA_HOME/cb00/folders. Failing to read or write it only loses the
+// setting, so errors are ignored.
+fn file() -> Option<PathBuf> {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+        .or_else(|| home::home_dir().map(|h| h.join(".local/share")))
+        .map(|dir| dir.join("cb00").join("folders"))
+}
+
+fn lines() -> Vec<String> {
+    file()
+        .and_then(|f| fs::read_to_string(f).ok())
+        .map(|s| s.lines().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+pub fn get(
+    url: &Url,
+    key: &str,
+) -> bool {
+    let line = format!("{} {}", key, url);
+    lines().contains(&line)
+}
+
+pub fn set(
+    url: &Url,
+    key: &str,
+    on: bool,
+) {
+    let file = match file() {
+        Some(file) => file,
+        None => return,
+    };
+    let line = format!("{} {}", key, url);
+    let mut lines = lines();
+    lines.retain(|l| l != &line);
+    if on {
+        lines.push(line);
+    }
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+    fs::write(file, lines.join("\n") + "\n").ok();
+}
@@ -1 +1,102 @@
+use super::common::Draw;
+use gfx_device_gl::{CommandBuffer, Resources};
+use gfx_graphics::GfxGraphics;
+use graphics::{
+    character::CharacterCache,
+    rectangle,
+    text::Text,
+    Context,
+    Transformed,
+};
+use piston_window::{Glyphs, PistonWindow};
+use sdl2_window::Sdl2Window;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
+const FONTS: [&str; 4] = [
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/noto/NotoSans-Regular.ttf",
+];
+const OSD_TIME: Duration = Duration::from_millis(1500);
+const TEXT_SIZE: u32 = 20;
+
+// Looks for `assets/font.ttf` next to the binary, then for an `assets` folder
+// near the working directory as `cargo run` has it, then in the usual system
+// locations. Without one the messages are simply not drawn.
+pub fn font(window: &mut PistonWindow<Sdl2Window>) -> Option<Glyphs> {
+    let installed = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join("assets")));
+    let nearby = find_folder::Search::ParentsThenKids(3, 3)
+        .for_folder("assets")
+        .ok();
+    installed
+        .into_iter()
+        .chain(nearby)
+        .map(|assets| assets.join("font.ttf"))
+        .chain(FONTS.iter().map(PathBuf::from))
+        .filter(|path| path.is_file())
+        .find_map(|path| window.load_font(path).ok())
+}
+
+// Short lived message drawn in the top left corner of the window.
+#[derive(Clone, Debug, Default)]
+pub struct Osd {
+    text:  String,
+    until: Option<Instant>,
+}
+impl Osd {
+    pub fn show(
+        &mut self,
+        text: impl Into<String>,
+    ) {
+        self.text = text.into();
+        self.until = Some(Instant::now() + OSD_TIME);
+    }
+
+    pub fn visible(&self) -> bool {
+        self.until.map(|t| t > Instant::now()).unwrap_or_default()
+    }
+}
+impl<'a> Draw<'a> for Osd {
+    type Params = &'a mut Glyphs;
+
+    fn draw(
+        &self,
+        c: Context,
+        g: &mut GfxGraphics<Resources, CommandBuffer>,
+        glyphs: Self::Params,
+    ) {
+        if self.visible() {
+            label(&self.text, c, g, glyphs);
+        }
+    }
+}
+
+// Height of a line drawn by `label`.
+pub fn line_height() -> f64 { TEXT_SIZE as f64 * 2. }
+
+// White text on a dark box, its top left corner at the origin of `c`.
+pub fn label(
+    text: &str,
+    c: Context,
+    g: &mut GfxGraphics<Resources, CommandBuffer>,
+    glyphs: &mut Glyphs,
+) {
+    let w = glyphs.width(TEXT_SIZE, text).unwrap_or_default();
+    let h = TEXT_SIZE as f64;
+    rectangle([0., 0., 0., 0.6], [0., 0., w + h, h * 2.], c.transform, g);
+    Text::new_color([1.; 4], TEXT_SIZE)
+        .draw(
+            text,
+            glyphs,
+            &c.draw_state,
+            c.transform.trans(h / 2., h * 1.5),
+            g,
+        )
+        .ok();
+}
@@ -0,0 +1,64 @@
+use notify::{
+    watcher,
+    DebouncedEvent,
+    RecommendedWatcher,
+    RecursiveMode,
+    Watcher,
+};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc,
+        Mutex,
+    },
+    time::Duration,
+};
+
+// Long enough for a downloader to finish writing a page before it is read.
+const SETTLE: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    Added(PathBuf),
+    Removed(PathBuf),
+    Modified(PathBuf),
+}
+
+// Shared so a cloned Folder keeps receiving from the same inotify watch.
+#[derive(Clone)]
+pub struct Watch(Arc<Mutex<(RecommendedWatcher, Receiver<DebouncedEvent>)>>);
+impl Watch {
+    pub fn new(dir: &Path) -> Option<Self> {
+        let (tx, rx) = channel();
+        let mut watcher = watcher(tx, SETTLE).ok()?;
+        watcher.watch(dir, RecursiveMode::NonRecursive).ok()?;
+        Some(Self(Arc::new(Mutex::new((watcher, rx)))))
+    }
+
+    pub fn changes(&self) -> Vec<Change> {
+        let inner = self.0.lock().unwrap();
+        inner
+            .1
+            .try_iter()
+            .flat_map(|event| match event {
+                DebouncedEvent::Create(p) => vec![Change::Added(p)],
+                DebouncedEvent::Write(p) => vec![Change::Modified(p)],
+                DebouncedEvent::Remove(p) => vec![Change::Removed(p)],
+                DebouncedEvent::Rename(from, to) => {
+                    vec![Change::Removed(from), Change::Added(to)]
+                }
+                _ => vec![],
+            })
+            .collect()
+    }
+}
+impl Debug for Watch {
+    fn fmt(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+    ) -> std::fmt::Result {
+        f.debug_tuple("Watch").finish()
+    }
+}
@@ -0,0 +1,185 @@
+use super::{
+    archive::{Archive, Entries},
+    decode::{dimensions, page, Limits, Page},
+    detect::{extension, sniff, Filter, Format},
+    error::Error,
+};
+use reqwest::Url;
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+        Mutex,
+    },
+};
+use tokio::runtime::Handle;
+
+// Bitmaps of every frame, still waiting for their upload to the GPU.
+pub type Decoded = Result<Page, Error>;
+// An archive along with its files, none when it can't be read.
+pub type Opened = Option<(Archive, Entries)>;
+
+// Bytes of an archive entry read to find its size. Headers that are further
+// in, like JPEGs after a large EXIF block, get their size once decoded.
+const PROBE_HEAD: u64 = 256 << 10;
+
+// Everything a worker needs to decode a page without touching the Picture.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub archive: Option<Archive>,
+    pub path:    PathBuf,
+    pub entry:   Option<String>,
+    pub format:  Option<Format>,
+    pub limits:  Limits,
+    // Of the page the job was queued for, see `Picture::version`.
+    pub version: u64,
+}
+impl Job {
+    pub fn run(self) -> Decoded {
+        let bytes = match (&self.archive, &self.entry) {
+            // Read one byte past the limit to tell whether it is over, a
+            // small entry can decompress to any size.
+            (Some(archive), Some(entry)) => {
+                let cap = self.limits.bytes;
+                let bytes = archive
+                    .head(entry, cap.saturating_add(1))
+                    .ok_or_else(|| Error::Entry(entry.clone()))?;
+                if bytes.len() as u64 > cap {
+                    return Err(Error::Limit(format!(
+                        "{} is over {} bytes",
+                        entry, cap
+                    )));
+                }
+                bytes
+            }
+            _ => std::fs::read(&self.path)
+                .map_err(|e| Error::Io(self.path.clone(), e))?,
+        };
+        let path = self.path;
+        let name = self
+            .entry
+            .unwrap_or_else(|| path.to_string_lossy().into_owned());
+        let format = self
+            .format
+            .or_else(|| sniff(&bytes, &extension(&name)))
+            .ok_or(Error::Format(name))?;
+        page(&bytes, format, &self.limits)
+    }
+
+    // Only the header, for the size of pages that aren't near the view yet.
+    pub fn probe(self) -> Option<(u32, u32)> {
+        let format = self.format?;
+        match (&self.archive, &self.entry) {
+            (Some(archive), Some(entry)) => {
+                let head = archive.head(entry, PROBE_HEAD)?;
+                dimensions(Cursor::new(head), format).ok()
+            }
+            _ => {
+                let file = File::open(&self.path).ok()?;
+                dimensions(BufReader::new(file), format).ok()
+            }
+        }
+    }
+}
+
+// Decodes on tokio's blocking threads, results are picked up by the render
+// thread with `done` since textures can only be created there. Sizes probed
+// ahead of decoding come back through `probed`, archives through `opened`.
+#[derive(Clone, Debug)]
+pub struct Pool {
+    tx:       Sender<(Url, u64, Decoded)>,
+    rx:       Arc<Mutex<Receiver<(Url, u64, Decoded)>>>,
+    sizes: (
+        Sender<(Url, u64, (u32, u32))>,
+        Arc<Mutex<Receiver<(Url, u64, (u32, u32))>>>,
+    ),
+    archives: (
+        Sender<(PathBuf, Opened)>,
+        Arc<Mutex<Receiver<(PathBuf, Opened)>>>,
+    ),
+}
+impl Default for Pool {
+    fn default() -> Self {
+        let (tx, rx) = channel();
+        let sizes = channel();
+        let archives = channel();
+        Self {
+            tx,
+            rx: Arc::new(Mutex::new(rx)),
+            sizes: (sizes.0, Arc::new(Mutex::new(sizes.1))),
+            archives: (archives.0, Arc::new(Mutex::new(archives.1))),
+        }
+    }
+}
+impl Pool {
+    pub fn decode(
+        &self,
+        url: Url,
+        job: Job,
+    ) {
+        let tx = self.tx.clone();
+        spawn(move || {
+            let version = job.version;
+            tx.send((url, version, job.run())).ok();
+        });
+    }
+
+    // Pages whose size can't be read are left out.
+    pub fn probe(
+        &self,
+        url: Url,
+        job: Job,
+    ) {
+        let tx = self.sizes.0.clone();
+        spawn(move || {
+            let version = job.version;
+            if let Some(size) = job.probe() {
+                tx.send((url, version, size)).ok();
+            }
+        });
+    }
+
+    // Indexing and listing both read through the archive, see
+    // `Archive::open`.
+    pub fn open(
+        &self,
+        path: PathBuf,
+        filter: Filter,
+        cap: u64,
+    ) {
+        let tx = self.archives.0.clone();
+        spawn(move || {
+            let opened = Archive::open(&path, cap).map(|archive| {
+                let entries = archive.entries(&filter);
+                (archive, entries)
+            });
+            tx.send((path, opened)).ok();
+        });
+    }
+
+    // Results come with the version of the page their job was queued for.
+    pub fn done(&self) -> Vec<(Url, u64, Decoded)> {
+        self.rx.lock().unwrap().try_iter().collect()
+    }
+
+    pub fn probed(&self) -> Vec<(Url, u64, (u32, u32))> {
+        self.sizes.1.lock().unwrap().try_iter().collect()
+    }
+
+    pub fn opened(&self) -> Vec<(PathBuf, Opened)> {
+        self.archives.1.lock().unwrap().try_iter().collect()
+    }
+}
+
+// Outside of a runtime the job runs right away on the calling thread.
+fn spawn(run: impl FnOnce() + Send + 'static) {
+    match Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn_blocking(run);
+        }
+        Err(_) => run(),
+    }
+}